@@ -1,4 +1,10 @@
-use criterion::{criterion_group, criterion_main, Criterion};
+use aoc2024::{
+    labelling::{label_components, Connectivity},
+    vec2d::Vec2d,
+    walker::Walker,
+    xorshift::xorshift,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 
 macro_rules! make_day_bench {
     ($c:expr, $day:ident) => {
@@ -39,5 +45,73 @@ fn criterion_benchmark(c: &mut Criterion) {
     make_day_bench!(c, day24);
 }
 
-criterion_group!(benches, criterion_benchmark);
+fn day6_loops_benchmark(c: &mut Criterion) {
+    let data = std::fs::read("inputs/day6.txt").unwrap();
+    let mut group = c.benchmark_group("day6_loops");
+    group.bench_function("find_possible_loops2", |b| {
+        b.iter(|| {
+            Walker::from_data(data.as_slice())
+                .unwrap()
+                .find_possible_loops2()
+        })
+    });
+    group.bench_function("find_possible_loops3", |b| {
+        b.iter(|| {
+            Walker::from_data(data.as_slice())
+                .unwrap()
+                .find_possible_loops3()
+        })
+    });
+    group.finish();
+}
+
+fn day9_whole_file_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("day9_whole_file");
+    for len in [2_000, 20_000] {
+        let mut state = 0x2024_u64;
+        let data = (0..len)
+            .map(|i| {
+                // Files can't be empty
+                b'0' + u8::try_from(xorshift(&mut state) % 9).unwrap() + u8::from(i % 2 == 0)
+            })
+            .collect::<Vec<_>>();
+
+        group.bench_with_input(BenchmarkId::new("part2_linear", len), &data, |b, data| {
+            b.iter(|| aoc2024::day9::part2_linear(data.as_slice()))
+        });
+        group.bench_with_input(BenchmarkId::new("part2", len), &data, |b, data| {
+            b.iter(|| aoc2024::day9::part2(data.as_slice()))
+        });
+    }
+    group.finish();
+}
+
+fn labelling_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("labelling");
+    group.sample_size(10);
+    for size in [1_000, 4_000] {
+        let mut state = 0x2024_u64;
+        let mut data = (0..size * size)
+            .map(|_| u8::from(xorshift(&mut state).is_multiple_of(3)))
+            .collect::<Vec<_>>();
+        let grid = Vec2d::new(&mut data, size, size);
+
+        for connectivity in [Connectivity::Four, Connectivity::Eight] {
+            group.bench_with_input(
+                BenchmarkId::new(format!("{connectivity:?}"), size),
+                &grid,
+                |b, grid| b.iter(|| label_components(grid, connectivity, |lhs, rhs| lhs == rhs)),
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    criterion_benchmark,
+    day6_loops_benchmark,
+    day9_whole_file_benchmark,
+    labelling_benchmark
+);
 criterion_main!(benches);
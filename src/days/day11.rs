@@ -1,9 +1,185 @@
 use std::{
-    collections::HashMap,
+    collections::{hash_map::Entry, HashMap},
+    error::Error,
+    fmt::Display,
+    hash::Hash,
     io::{BufRead, BufReader, Read},
 };
 
-fn parse_stones(reader: impl Read) -> HashMap<u64, usize> {
+use num_bigint::BigUint;
+
+type StonePredicate<T> = Box<dyn Fn(&T) -> bool>;
+// `None` if the new stones can't be represented by `T`
+type StoneTransform<T> = Box<dyn Fn(&T) -> Option<Vec<T>>>;
+
+// Rules are tried in order, the first one whose predicate matches replaces the
+// stone with the stones returned by its transformation
+pub struct StoneRules<T = u64> {
+    rules: Vec<(StonePredicate<T>, StoneTransform<T>)>,
+}
+
+impl<T> Default for StoneRules<T> {
+    fn default() -> Self {
+        Self { rules: Vec::new() }
+    }
+}
+
+impl<T> StoneRules<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_rule(
+        self,
+        predicate: impl Fn(&T) -> bool + 'static,
+        transform: impl Fn(&T) -> Vec<T> + 'static,
+    ) -> Self {
+        self.with_checked_rule(predicate, move |stone| Some(transform(stone)))
+    }
+
+    pub fn with_checked_rule(
+        mut self,
+        predicate: impl Fn(&T) -> bool + 'static,
+        transform: impl Fn(&T) -> Option<Vec<T>> + 'static,
+    ) -> Self {
+        self.rules.push((Box::new(predicate), Box::new(transform)));
+        self
+    }
+
+    // Stones that match no rule are left unchanged
+    pub fn apply(&self, stone: &T) -> Vec<T>
+    where
+        T: Clone,
+    {
+        self.try_apply(stone).expect("Stone value overflowed")
+    }
+
+    pub fn try_apply(&self, stone: &T) -> Option<Vec<T>>
+    where
+        T: Clone,
+    {
+        self.rules
+            .iter()
+            .find(|(predicate, _)| predicate(stone))
+            .map_or_else(
+                || Some(vec![stone.clone()]),
+                |(_, transform)| transform(stone),
+            )
+    }
+}
+
+impl<T: PuzzleStone> StoneRules<T> {
+    pub fn puzzle() -> Self {
+        T::puzzle_rules()
+    }
+}
+
+pub trait PuzzleStone: Sized {
+    fn puzzle_rules() -> StoneRules<Self>;
+}
+
+impl PuzzleStone for u64 {
+    fn puzzle_rules() -> StoneRules<Self> {
+        StoneRules::<u64>::new()
+            .with_rule(|n| *n == 0, |_| vec![1])
+            .with_rule(
+                |n| n.ilog10() % 2 == 1,
+                |n| {
+                    let half = 10u64.pow(n.ilog10().div_ceil(2));
+                    vec![n / half, n % half]
+                },
+            )
+            .with_checked_rule(|_| true, |n| n.checked_mul(2024).map(|n| vec![n]))
+    }
+}
+
+impl PuzzleStone for BigUint {
+    fn puzzle_rules() -> StoneRules<Self> {
+        StoneRules::<BigUint>::new()
+            .with_rule(|n| *n == BigUint::ZERO, |_| vec![BigUint::from(1u8)])
+            .with_rule(
+                |n| n.to_string().len() % 2 == 0,
+                |n| {
+                    let digits = n.to_string();
+                    let (l, r) = digits.split_at(digits.len() / 2);
+                    vec![l.parse().unwrap(), r.parse().unwrap()]
+                },
+            )
+            .with_rule(|_| true, |n| vec![n * 2024u32])
+    }
+}
+
+pub trait StoneCount: Clone {
+    fn zero() -> Self;
+
+    fn one() -> Self;
+
+    fn try_add(&self, rhs: &Self) -> Option<Self>;
+}
+
+impl StoneCount for usize {
+    fn zero() -> Self {
+        0
+    }
+
+    fn one() -> Self {
+        1
+    }
+
+    fn try_add(&self, rhs: &Self) -> Option<Self> {
+        self.checked_add(*rhs)
+    }
+}
+
+impl StoneCount for BigUint {
+    fn zero() -> Self {
+        BigUint::ZERO
+    }
+
+    fn one() -> Self {
+        BigUint::from(1u8)
+    }
+
+    fn try_add(&self, rhs: &Self) -> Option<Self> {
+        Some(self + rhs)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvolveError {
+    // Blinks are counted from 1
+    ValueOverflow { blink: usize },
+    CountOverflow { blink: usize },
+}
+
+impl Display for EvolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ValueOverflow { blink } => {
+                write!(f, "Stone value overflowed on blink {blink}")
+            }
+            Self::CountOverflow { blink } => {
+                write!(f, "Stone count overflowed on blink {blink}")
+            }
+        }
+    }
+}
+
+impl Error for EvolveError {}
+
+pub fn parse_stones(reader: impl Read) -> HashMap<u64, usize> {
+    parse_stones_as(reader)
+}
+
+pub fn parse_big_stones(reader: impl Read) -> HashMap<BigUint, BigUint> {
+    parse_stones_as(reader)
+}
+
+fn parse_stones_as<T, C>(reader: impl Read) -> HashMap<T, C>
+where
+    T: Hash + Eq + std::str::FromStr<Err: std::fmt::Debug>,
+    C: StoneCount,
+{
     let mut res = HashMap::new();
     BufReader::new(reader)
         .lines()
@@ -11,62 +187,498 @@ fn parse_stones(reader: impl Read) -> HashMap<u64, usize> {
         .unwrap()
         .unwrap()
         .split_ascii_whitespace()
-        .map(|value| value.parse::<u64>().unwrap())
+        .map(|value| value.parse::<T>().unwrap())
         .fold(&mut res, |map, value| {
-            map.entry(value)
-                .and_modify(|count| *count += 1)
-                .or_insert(1usize);
+            push_stone(map, value, &C::one()).unwrap();
             map
         });
     res
 }
 
-fn push_stone(stones: &mut HashMap<u64, usize>, value: u64, previous_count: usize) {
-    stones
-        .entry(value)
-        .and_modify(|count| *count += previous_count)
-        .or_insert(previous_count);
+fn push_stone<T: Hash + Eq, C: StoneCount>(
+    stones: &mut HashMap<T, C>,
+    value: T,
+    previous_count: &C,
+) -> Option<()> {
+    match stones.entry(value) {
+        Entry::Occupied(mut entry) => {
+            let count = entry.get().try_add(previous_count)?;
+            entry.insert(count);
+        }
+        Entry::Vacant(entry) => {
+            entry.insert(previous_count.clone());
+        }
+    }
+    Some(())
+}
+
+pub fn evolve<T: Hash + Eq + Clone, C: StoneCount>(
+    stones: &mut HashMap<T, C>,
+    blinks: usize,
+    rules: &StoneRules<T>,
+) {
+    if let Err(err) = try_evolve(stones, blinks, rules) {
+        panic!("{err}");
+    }
 }
 
-fn evolve(stones: &mut HashMap<u64, usize>, blinks: usize) {
-    for _ in 0..blinks {
+// On error, `stones` is left as it was before the failing blink
+pub fn try_evolve<T: Hash + Eq + Clone, C: StoneCount>(
+    stones: &mut HashMap<T, C>,
+    blinks: usize,
+    rules: &StoneRules<T>,
+) -> Result<(), EvolveError> {
+    for i in 1..=blinks {
         let mut buffer = HashMap::new();
-        blink(stones, &mut buffer);
+        blink(stones, &mut buffer, rules, i)?;
         std::mem::swap(stones, &mut buffer);
     }
+    Ok(())
 }
 
-fn blink(stones: &mut HashMap<u64, usize>, buffer: &mut HashMap<u64, usize>) {
+fn blink<T: Hash + Eq + Clone, C: StoneCount>(
+    stones: &HashMap<T, C>,
+    buffer: &mut HashMap<T, C>,
+    rules: &StoneRules<T>,
+    i: usize,
+) -> Result<(), EvolveError> {
     for (value, stone_count) in stones.iter() {
-        match *value {
-            0 => {
-                push_stone(buffer, 1, *stone_count);
+        let new_stones = rules
+            .try_apply(value)
+            .ok_or(EvolveError::ValueOverflow { blink: i })?;
+        for stone in new_stones {
+            push_stone(buffer, stone, stone_count)
+                .ok_or(EvolveError::CountOverflow { blink: i })?;
+        }
+    }
+    Ok(())
+}
+
+// `None` if the total overflows
+pub fn count_stones<C: StoneCount>(stones: &HashMap<impl Hash + Eq, C>) -> Option<C> {
+    stones
+        .values()
+        .try_fold(C::zero(), |sum, count| sum.try_add(count))
+}
+
+// The values reachable from a set of stones, and how many stones of each value
+// a stone turns into after one blink
+#[derive(Debug, Clone)]
+pub struct StoneTransitions<T> {
+    values: Vec<T>,
+    indexes: HashMap<T, usize>,
+    // For each value, the indexes of the values it turns into with how many
+    // stones of that value are made
+    transitions: Vec<Vec<(usize, u64)>>,
+}
+
+impl<T: Hash + Eq + Clone> StoneTransitions<T> {
+    const WARMUP: u64 = 200;
+
+    // `None` if more than `limit` values are reachable, or if a value overflows
+    pub fn build(
+        initial: impl IntoIterator<Item = T>,
+        rules: &StoneRules<T>,
+        limit: usize,
+    ) -> Option<Self> {
+        let mut stone_transitions = Self {
+            values: Vec::new(),
+            indexes: HashMap::new(),
+            transitions: Vec::new(),
+        };
+        for value in initial {
+            stone_transitions.index_of(value);
+        }
+
+        while let Some(value) = stone_transitions
+            .values
+            .get(stone_transitions.transitions.len())
+        {
+            if stone_transitions.values.len() > limit {
+                return None;
             }
-            n => {
-                let log = n.ilog10();
-                if log % 2 == 1 {
-                    let log = 10u64.pow(log.div_ceil(2));
-                    let l = n / log;
-                    let r = n % log;
-
-                    push_stone(buffer, l, *stone_count);
-                    push_stone(buffer, r, *stone_count);
-                } else {
-                    push_stone(buffer, n * 2024, *stone_count);
+
+            let mut transition: Vec<(usize, u64)> = Vec::new();
+            for stone in rules.try_apply(value)? {
+                let index = stone_transitions.index_of(stone);
+                match transition.iter_mut().find(|(to, _)| *to == index) {
+                    Some((_, count)) => *count += 1,
+                    None => transition.push((index, 1)),
                 }
             }
+            stone_transitions.transitions.push(transition);
+        }
+
+        Some(stone_transitions)
+    }
+
+    fn index_of(&mut self, value: T) -> usize {
+        match self.indexes.entry(value) {
+            Entry::Occupied(entry) => *entry.get(),
+            Entry::Vacant(entry) => {
+                self.values.push(entry.key().clone());
+                *entry.insert(self.values.len() - 1)
+            }
+        }
+    }
+
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    // `matrix[to][from]` is how many stones of value `to` a stone of value
+    // `from` turns into
+    pub fn matrix(&self) -> Vec<Vec<u64>> {
+        let mut matrix = vec![vec![0; self.len()]; self.len()];
+        for (from, transition) in self.transitions.iter().enumerate() {
+            for (to, count) in transition {
+                matrix[*to][from] += count;
+            }
+        }
+        matrix
+    }
+
+    fn initial_vector(&self, stones: &HashMap<T, usize>) -> Vec<u64> {
+        let mut vector = vec![0; self.len()];
+        for (value, count) in stones {
+            let index = *self
+                .indexes
+                .get(value)
+                .expect("Stone must be part of the transitions");
+            vector[index] += u64::try_from(*count).unwrap();
+        }
+        vector
+    }
+
+    // Stone count after `blinks` modulo `modulus`. When `modulus` is prime,
+    // the counts of n reachable values follow a linear recurrence of order at
+    // most n, that the first 2n counts give with Berlekamp-Massey. The count
+    // after `blinks` is then read from x^blinks modulo the recurrence's
+    // characteristic polynomial, in O(n² log(blinks)) at worst. Other moduli
+    // go through `count_after_by_matrix`.
+    pub fn count_after(&self, stones: &HashMap<T, usize>, blinks: u64, modulus: u64) -> u64 {
+        if !is_prime(modulus) {
+            return self.count_after_by_matrix(stones, blinks, modulus);
+        }
+        let modulus = u128::from(modulus);
+
+        let terms = usize::try_from(blinks)
+            .map_or(usize::MAX, |blinks| blinks.saturating_add(1))
+            .min(2 * self.len());
+        let counts = self.first_counts(stones, terms, modulus);
+        if let Some(count) = usize::try_from(blinks)
+            .ok()
+            .and_then(|blinks| counts.get(blinks))
+        {
+            return u64::try_from(*count).unwrap();
+        }
+
+        let recurrence = berlekamp_massey(&counts, modulus);
+        let remainder = power_of_x(blinks, &recurrence, modulus);
+        u64::try_from(
+            remainder
+                .into_iter()
+                .zip(counts)
+                .fold(0, |sum, (coefficient, count)| {
+                    (sum + coefficient * count) % modulus
+                }),
+        )
+        .unwrap()
+    }
+
+    // Stone count after `blinks` modulo `modulus` by exponentiating the
+    // transition matrix, in O(n³ log(blinks)) for n reachable values
+    pub fn count_after_by_matrix(
+        &self,
+        stones: &HashMap<T, usize>,
+        blinks: u64,
+        modulus: u64,
+    ) -> u64 {
+        let modulus = u128::from(modulus);
+        let mut vector = self
+            .initial_vector(stones)
+            .into_iter()
+            .map(|count| u128::from(count) % modulus)
+            .collect::<Vec<_>>();
+        let mut power = self
+            .matrix()
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|count| u128::from(count) % modulus)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut blinks = blinks;
+        while blinks > 0 {
+            if blinks & 1 == 1 {
+                vector = mul_matrix_vector(&power, &vector, modulus);
+            }
+            blinks >>= 1;
+            if blinks > 0 {
+                power = mul_matrix_matrix(&power, &power, modulus);
+            }
+        }
+
+        u64::try_from(
+            vector
+                .into_iter()
+                .fold(0, |sum, count| (sum + count) % modulus),
+        )
+        .unwrap()
+    }
+
+    // Stone counts modulo `modulus` after 0 to `terms - 1` blinks
+    fn first_counts(&self, stones: &HashMap<T, usize>, terms: usize, modulus: u128) -> Vec<u128> {
+        let mut vector = self
+            .initial_vector(stones)
+            .into_iter()
+            .map(|count| u128::from(count) % modulus)
+            .collect::<Vec<_>>();
+
+        let mut counts = Vec::with_capacity(terms);
+        for _ in 0..terms {
+            counts.push(vector.iter().fold(0, |sum, count| (sum + count) % modulus));
+
+            // Most values only show up during the first blinks, so the vector
+            // quickly becomes sparse. Counts are below 2^64, and there are far
+            // fewer than 2^32 values and stones made by a rule, so the sums
+            // are only reduced once.
+            let mut next = vec![0; self.len()];
+            for (from, count) in vector.iter().enumerate().filter(|(_, count)| **count != 0) {
+                for (to, times) in &self.transitions[from] {
+                    next[*to] += count * u128::from(*times);
+                }
+            }
+            next.iter_mut()
+                .filter(|count| **count >= modulus)
+                .for_each(|count| *count %= modulus);
+            vector = next;
+        }
+        counts
+    }
+
+    // Average factor by which the stone count grows on each blink, once the
+    // values have settled
+    pub fn growth_rate(&self) -> f64 {
+        let mut vector = vec![1.; self.len()];
+        let mut log10_growth = 0.;
+        for i in 0..Self::WARMUP {
+            let log10_norm = self.step_normalized(&mut vector);
+            if i >= Self::WARMUP / 2 {
+                log10_growth += log10_norm;
+            }
+        }
+        10f64.powf(log10_growth / (Self::WARMUP / 2) as f64)
+    }
+
+    // Base 10 logarithm of the estimated stone count after `blinks`
+    pub fn estimate_log10_count(&self, stones: &HashMap<T, usize>, blinks: u64) -> f64 {
+        let mut vector = self
+            .initial_vector(stones)
+            .into_iter()
+            .map(|count| count as f64)
+            .collect::<Vec<_>>();
+        let sum = vector.iter().sum::<f64>();
+        vector.iter_mut().for_each(|count| *count /= sum);
+
+        let mut log10_count = sum.log10();
+        for _ in 0..blinks.min(Self::WARMUP) {
+            log10_count += self.step_normalized(&mut vector);
+        }
+        if blinks > Self::WARMUP {
+            log10_count += (blinks - Self::WARMUP) as f64 * self.growth_rate().log10();
+        }
+        log10_count
+    }
+
+    // Blinks once, and scales `vector` back to a sum of 1, returning the base 10
+    // logarithm of the scale
+    fn step_normalized(&self, vector: &mut Vec<f64>) -> f64 {
+        let mut next = vec![0.; self.len()];
+        for (from, transition) in self.transitions.iter().enumerate() {
+            for (to, count) in transition {
+                next[*to] += vector[from] * *count as f64;
+            }
+        }
+        let sum = next.iter().sum::<f64>();
+        next.iter_mut().for_each(|count| *count /= sum);
+        *vector = next;
+        sum.log10()
+    }
+}
+
+fn mul_matrix_vector(matrix: &[Vec<u128>], vector: &[u128], modulus: u128) -> Vec<u128> {
+    matrix
+        .iter()
+        .map(|row| {
+            row.iter()
+                .zip(vector)
+                .filter(|(lhs, _)| **lhs != 0)
+                .fold(0, |sum, (lhs, rhs)| (sum + lhs * rhs % modulus) % modulus)
+        })
+        .collect()
+}
+
+fn mul_matrix_matrix(lhs: &[Vec<u128>], rhs: &[Vec<u128>], modulus: u128) -> Vec<Vec<u128>> {
+    let mut res = vec![vec![0; rhs.first().map_or(0, Vec::len)]; lhs.len()];
+    for (res_row, lhs_row) in res.iter_mut().zip(lhs) {
+        for (lhs, rhs_row) in lhs_row.iter().zip(rhs).filter(|(lhs, _)| **lhs != 0) {
+            for (res, rhs) in res_row.iter_mut().zip(rhs_row) {
+                *res = (*res + lhs * rhs % modulus) % modulus;
+            }
+        }
+    }
+    res
+}
+
+fn pow_mod(base: u128, exponent: u128, modulus: u128) -> u128 {
+    let mut result = 1 % modulus;
+    let mut base = base % modulus;
+    let mut exponent = exponent;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = result * base % modulus;
+        }
+        base = base * base % modulus;
+        exponent >>= 1;
+    }
+    result
+}
+
+// Deterministic Miller-Rabin, these bases are enough for any u64
+fn is_prime(n: u64) -> bool {
+    const BASES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+    if n < 2 {
+        return false;
+    }
+    if let Some(base) = BASES.iter().find(|base| n.is_multiple_of(**base)) {
+        return n == *base;
+    }
+
+    let n = u128::from(n);
+    let shift = (n - 1).trailing_zeros();
+    let odd = (n - 1) >> shift;
+    BASES.iter().all(|base| {
+        let mut x = pow_mod(u128::from(*base), odd, n);
+        if x == 1 || x == n - 1 {
+            return true;
+        }
+        (1..shift).any(|_| {
+            x = x * x % n;
+            x == n - 1
+        })
+    })
+}
+
+// Shortest `c` such that `sequence[i] = Σ c[j] * sequence[i - 1 - j]` for
+// every `i >= c.len()`, modulo the prime `modulus`
+fn berlekamp_massey(sequence: &[u128], modulus: u128) -> Vec<u128> {
+    // Connection polynomials, the current one and the one before the last
+    // length change
+    let mut current = vec![1];
+    let mut previous = vec![1];
+    let mut len = 0;
+    let mut previous_discrepancy = 1;
+    let mut shift = 0;
+
+    for (i, term) in sequence.iter().enumerate() {
+        shift += 1;
+        let discrepancy = (1..current.len()).fold(*term, |discrepancy, j| {
+            (discrepancy + current[j] * sequence[i - j]) % modulus
+        });
+        if discrepancy == 0 {
+            continue;
+        }
+
+        let before = (2 * len <= i).then(|| current.clone());
+        let coefficient =
+            discrepancy * pow_mod(previous_discrepancy, modulus - 2, modulus) % modulus;
+        if current.len() < previous.len() + shift {
+            current.resize(previous.len() + shift, 0);
+        }
+        for (j, previous) in previous.iter().enumerate() {
+            current[j + shift] =
+                (current[j + shift] + modulus - coefficient * previous % modulus) % modulus;
+        }
+        if let Some(before) = before {
+            len = i + 1 - len;
+            previous = before;
+            previous_discrepancy = discrepancy;
+            shift = 0;
+        }
+    }
+
+    current.resize(len + 1, 0);
+    current[1..]
+        .iter()
+        .map(|coefficient| (modulus - coefficient) % modulus)
+        .collect()
+}
+
+// x^exponent modulo x^n - Σ recurrence[j] * x^(n - 1 - j), as its n
+// coefficients from the lowest degree
+fn power_of_x(exponent: u64, recurrence: &[u128], modulus: u128) -> Vec<u128> {
+    if recurrence.is_empty() {
+        return Vec::new();
+    }
+
+    let mut power = vec![0; recurrence.len()];
+    power[0] = 1;
+    for bit in (0..u64::BITS - exponent.leading_zeros()).rev() {
+        power = square_polynomial(&power, recurrence, modulus);
+        if exponent >> bit & 1 == 1 {
+            power.insert(0, 0);
+            power = reduce_polynomial(power, recurrence, modulus);
+        }
+    }
+    power
+}
+
+fn square_polynomial(polynomial: &[u128], recurrence: &[u128], modulus: u128) -> Vec<u128> {
+    let mut square = vec![0; 2 * polynomial.len() - 1];
+    for (i, lhs) in polynomial.iter().enumerate().filter(|(_, lhs)| **lhs != 0) {
+        square[2 * i] = (square[2 * i] + lhs * lhs) % modulus;
+        let double = 2 * lhs % modulus;
+        for (j, rhs) in polynomial.iter().enumerate().skip(i + 1) {
+            square[i + j] = (square[i + j] + double * rhs) % modulus;
+        }
+    }
+    reduce_polynomial(square, recurrence, modulus)
+}
+
+// Uses x^i = Σ recurrence[j] * x^(i - 1 - j) for i >= n
+fn reduce_polynomial(mut polynomial: Vec<u128>, recurrence: &[u128], modulus: u128) -> Vec<u128> {
+    for i in (recurrence.len()..polynomial.len()).rev() {
+        let coefficient = polynomial[i];
+        if coefficient == 0 {
+            continue;
+        }
+        for (j, term) in recurrence.iter().enumerate() {
+            polynomial[i - 1 - j] = (polynomial[i - 1 - j] + coefficient * term) % modulus;
         }
     }
+    polynomial.truncate(recurrence.len());
+    polynomial
 }
 
 pub fn part1(reader: impl Read) -> usize {
     let mut stones = parse_stones(reader);
-    evolve(&mut stones, 25);
-    stones.into_values().sum()
+    evolve(&mut stones, 25, &StoneRules::puzzle());
+    count_stones(&stones).expect("Stone count overflowed")
 }
 
 pub fn part2(reader: impl Read) -> usize {
     let mut stones = parse_stones(reader);
-    evolve(&mut stones, 75);
-    stones.into_values().sum()
+    evolve(&mut stones, 75, &StoneRules::puzzle());
+    count_stones(&stones).expect("Stone count overflowed")
 }
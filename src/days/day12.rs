@@ -1,17 +1,46 @@
-use std::{
-    cell::Cell,
-    collections::HashMap,
-    io::Read,
-    ops::{Deref, DerefMut},
-    rc::Rc,
+use std::{collections::BTreeSet, io::Read};
+
+use crate::{
+    coord::Coord,
+    disjoint_set::DisjointSet,
+    labelling::{label_components, Connectivity},
+    vec2d::Vec2d,
 };
 
+// Area and perimeter, or area and sides, of the region of each cell
+type RegionSet = DisjointSet<(usize, usize), (u64, u64)>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub plant: u8,
+    pub cells: BTreeSet<Coord>,
+    pub area: u64,
+    pub perimeter: u64,
+    pub sides: u64,
+    // Top left and bottom right corners, both inclusive
+    pub bounding_box: (Coord, Coord),
+    // Indexes of the regions enclosed by this one
+    pub holes: BTreeSet<usize>,
+    // Indexes of the regions sharing an edge with this one
+    pub adjacent: BTreeSet<usize>,
+}
+
+fn merge_regions(lhs: (u64, u64), rhs: (u64, u64)) -> (u64, u64) {
+    (lhs.0 + rhs.0, lhs.1 + rhs.1)
+}
+
 fn parse_data(data: &[u8]) -> Vec<&[u8]> {
-    data.split(|c| c == &b'\n').collect()
+    data.split(|c| c == &b'\n')
+        .filter(|line| !line.is_empty())
+        .collect()
 }
 
 fn calculate_area_perimeter(data: &[&[u8]]) -> Vec<(u64, u64)> {
-    let mut merge_insert = MergeInsert::default();
+    area_perimeter_disjoint_set(data).into_values().collect()
+}
+
+fn area_perimeter_disjoint_set(data: &[&[u8]]) -> RegionSet {
+    let mut disjoint_set = RegionSet::new(merge_regions);
 
     for convolution in Convolution::new(data) {
         let ([[_, t, _], [l, c, r], [_, b, _]], line, col) = convolution;
@@ -21,35 +50,33 @@ fn calculate_area_perimeter(data: &[&[u8]]) -> Vec<(u64, u64)> {
 
         match (c == l, c == t) {
             (true, true) => {
-                merge_insert.insert((line, col), (cur_area, cur_perimeter));
-                merge_insert.merge((line, col), (line, col - 1));
-                merge_insert.merge((line, col), (line - 1, col));
+                disjoint_set.insert((line, col), (cur_area, cur_perimeter));
+                disjoint_set.union(&(line, col), &(line, col - 1));
+                disjoint_set.union(&(line, col), &(line - 1, col));
             }
             (true, false) => {
-                merge_insert.insert((line, col), (cur_area, cur_perimeter));
-                merge_insert.merge((line, col), (line, col - 1));
+                disjoint_set.insert((line, col), (cur_area, cur_perimeter));
+                disjoint_set.union(&(line, col), &(line, col - 1));
             }
             (false, true) => {
-                merge_insert.insert((line, col), (cur_area, cur_perimeter));
-                merge_insert.merge((line, col), (line - 1, col));
+                disjoint_set.insert((line, col), (cur_area, cur_perimeter));
+                disjoint_set.union(&(line, col), &(line - 1, col));
             }
             (false, false) => {
-                merge_insert.insert((line, col), (cur_area, cur_perimeter));
+                disjoint_set.insert((line, col), (cur_area, cur_perimeter));
             }
         }
     }
 
-    merge_insert
-        .drain()
-        .filter_map(|(_, v)| match v {
-            MergeInsertNode::Root(v) => Some(v.get()),
-            _ => None,
-        })
-        .collect()
+    disjoint_set
 }
 
 fn calculate_sides(data: &[&[u8]]) -> Vec<(u64, u64)> {
-    let mut merge_insert = MergeInsert::default();
+    sides_disjoint_set(data).into_values().collect()
+}
+
+fn sides_disjoint_set(data: &[&[u8]]) -> RegionSet {
+    let mut disjoint_set = RegionSet::new(merge_regions);
 
     for (convolution, line, col) in Convolution::new(data) {
         let c = convolution[1][1];
@@ -58,276 +85,396 @@ fn calculate_sides(data: &[&[u8]]) -> Vec<(u64, u64)> {
             [_, [_, false, _], _] => unreachable!("Center is always equal to center."),
             // No adjacents
             [[_, false, _], [false, true, false], [_, false, _]] => {
-                merge_insert.insert((line, col), (1, 4));
+                disjoint_set.insert((line, col), (1, 4));
             }
             // Four way adjacents
             [[_, true, _], [true, true, true], [_, true, _]] => {
-                merge_insert.insert((line, col), (1, 0));
-                merge_insert.merge((line, col), (line - 1, col));
-                merge_insert.merge((line, col), (line, col - 1));
+                disjoint_set.insert((line, col), (1, 0));
+                disjoint_set.union(&(line, col), &(line - 1, col));
+                disjoint_set.union(&(line, col), &(line, col - 1));
             }
             // XAX
             // XAX
             // XXX
             [[false, true, false], [false, true, false], [_, false, _]] => {
-                merge_insert.insert((line, col), (1, 1));
-                merge_insert.merge((line, col), (line - 1, col));
+                disjoint_set.insert((line, col), (1, 1));
+                disjoint_set.union(&(line, col), &(line - 1, col));
             }
             // AAX
             // XAX
             // XXX
             [[true, true, false], [false, true, false], [_, false, _]] => {
-                merge_insert.insert((line, col), (1, 2));
-                merge_insert.merge((line, col), (line - 1, col));
+                disjoint_set.insert((line, col), (1, 2));
+                disjoint_set.union(&(line, col), &(line - 1, col));
             }
             // XAA
             // XAX
             // XXX
             [[false, true, true], [false, true, false], [_, false, _]] => {
-                merge_insert.insert((line, col), (1, 2));
-                merge_insert.merge((line, col), (line - 1, col));
+                disjoint_set.insert((line, col), (1, 2));
+                disjoint_set.union(&(line, col), &(line - 1, col));
             }
             // AAA
             // XAX
             // XXX
             [[true, true, true], [false, true, false], [_, false, _]] => {
-                merge_insert.insert((line, col), (1, 3));
-                merge_insert.merge((line, col), (line - 1, col));
+                disjoint_set.insert((line, col), (1, 3));
+                disjoint_set.union(&(line, col), &(line - 1, col));
             }
             // XXX
             // AAX
             // XXX
             [[false, false, _], [true, true, false], [false, false, _]] => {
-                merge_insert.insert((line, col), (1, 1));
-                merge_insert.merge((line, col), (line, col - 1));
+                disjoint_set.insert((line, col), (1, 1));
+                disjoint_set.union(&(line, col), &(line, col - 1));
             }
             // AXX
             // AAX
             // XXX
             [[true, false, _], [true, true, false], [false, false, _]] => {
-                merge_insert.insert((line, col), (1, 2));
-                merge_insert.merge((line, col), (line, col - 1));
+                disjoint_set.insert((line, col), (1, 2));
+                disjoint_set.union(&(line, col), &(line, col - 1));
             }
             // XXX
             // AAX
             // AXX
             [[false, false, _], [true, true, false], [true, false, _]] => {
-                merge_insert.insert((line, col), (1, 2));
-                merge_insert.merge((line, col), (line, col - 1));
+                disjoint_set.insert((line, col), (1, 2));
+                disjoint_set.union(&(line, col), &(line, col - 1));
             }
             // AXX
             // AAX
             // AXX
             [[true, false, _], [true, true, false], [true, false, _]] => {
-                merge_insert.insert((line, col), (1, 3));
-                merge_insert.merge((line, col), (line, col - 1));
+                disjoint_set.insert((line, col), (1, 3));
+                disjoint_set.union(&(line, col), &(line, col - 1));
             }
             // XXX
             // XAX
             // XAX
             [[_, false, _], [false, true, false], [_, true, _]] => {
-                merge_insert.insert((line, col), (1, 3));
+                disjoint_set.insert((line, col), (1, 3));
             }
             // XXX
             // XAA
             // XXX
             [[_, false, _], [false, true, true], [_, false, _]] => {
-                merge_insert.insert((line, col), (1, 3));
+                disjoint_set.insert((line, col), (1, 3));
             }
             // XAX
             // AAX
             // XXX
             [[_, true, false], [true, true, false], [false, false, _]] => {
-                merge_insert.insert((line, col), (1, 0));
-                merge_insert.merge((line, col), (line - 1, col));
-                merge_insert.merge((line, col), (line, col - 1));
+                disjoint_set.insert((line, col), (1, 0));
+                disjoint_set.union(&(line, col), &(line - 1, col));
+                disjoint_set.union(&(line, col), &(line, col - 1));
             }
             // XAA
             // AAX
             // XXX
             [[_, true, true], [true, true, false], [false, false, _]] => {
-                merge_insert.insert((line, col), (1, 1));
-                merge_insert.merge((line, col), (line - 1, col));
-                merge_insert.merge((line, col), (line, col - 1));
+                disjoint_set.insert((line, col), (1, 1));
+                disjoint_set.union(&(line, col), &(line - 1, col));
+                disjoint_set.union(&(line, col), &(line, col - 1));
             }
             // XAX
             // AAX
             // AXX
             [[_, true, false], [true, true, false], [true, false, _]] => {
-                merge_insert.insert((line, col), (1, 1));
-                merge_insert.merge((line, col), (line - 1, col));
-                merge_insert.merge((line, col), (line, col - 1));
+                disjoint_set.insert((line, col), (1, 1));
+                disjoint_set.union(&(line, col), &(line - 1, col));
+                disjoint_set.union(&(line, col), &(line, col - 1));
             }
             // XAA
             // AAX
             // AXX
             [[_, true, true], [true, true, false], [true, false, _]] => {
-                merge_insert.insert((line, col), (1, 2));
-                merge_insert.merge((line, col), (line - 1, col));
-                merge_insert.merge((line, col), (line, col - 1));
+                disjoint_set.insert((line, col), (1, 2));
+                disjoint_set.union(&(line, col), &(line - 1, col));
+                disjoint_set.union(&(line, col), &(line, col - 1));
             }
             // XAX
             // XAX
             // XAX
             [[false, true, false], [false, true, false], [_, true, _]] => {
-                merge_insert.insert((line, col), (1, 0));
-                merge_insert.merge((line, col), (line - 1, col));
+                disjoint_set.insert((line, col), (1, 0));
+                disjoint_set.union(&(line, col), &(line - 1, col));
             }
             // AAX
             // XAX
             // XAX
             [[true, true, false], [false, true, false], [_, true, _]] => {
-                merge_insert.insert((line, col), (1, 1));
-                merge_insert.merge((line, col), (line - 1, col));
+                disjoint_set.insert((line, col), (1, 1));
+                disjoint_set.union(&(line, col), &(line - 1, col));
             }
             // XAA
             // XAX
             // XAX
             [[false, true, true], [false, true, false], [_, true, _]] => {
-                merge_insert.insert((line, col), (1, 1));
-                merge_insert.merge((line, col), (line - 1, col));
+                disjoint_set.insert((line, col), (1, 1));
+                disjoint_set.union(&(line, col), &(line - 1, col));
             }
             // AAA
             // XAX
             // XAX
             [[true, true, true], [false, true, false], [_, true, _]] => {
-                merge_insert.insert((line, col), (1, 2));
-                merge_insert.merge((line, col), (line - 1, col));
+                disjoint_set.insert((line, col), (1, 2));
+                disjoint_set.union(&(line, col), &(line - 1, col));
             }
             // XAX
             // XAA
             // XXX
             [[false, true, _], [false, true, true], [_, false, _]] => {
-                merge_insert.insert((line, col), (1, 1));
-                merge_insert.merge((line, col), (line - 1, col));
+                disjoint_set.insert((line, col), (1, 1));
+                disjoint_set.union(&(line, col), &(line - 1, col));
             }
             // AAX
             // XAA
             // XXX
             [[true, true, _], [false, true, true], [_, false, _]] => {
-                merge_insert.insert((line, col), (1, 2));
-                merge_insert.merge((line, col), (line - 1, col));
+                disjoint_set.insert((line, col), (1, 2));
+                disjoint_set.union(&(line, col), &(line - 1, col));
             }
             // XXX
             // AAX
             // XAX
             [[false, false, _], [true, true, false], [_, true, _]] => {
-                merge_insert.insert((line, col), (1, 1));
-                merge_insert.merge((line, col), (line, col - 1));
+                disjoint_set.insert((line, col), (1, 1));
+                disjoint_set.union(&(line, col), &(line, col - 1));
             }
             // AXX
             // AAX
             // XAX
             [[true, false, _], [true, true, false], [_, true, _]] => {
-                merge_insert.insert((line, col), (1, 2));
-                merge_insert.merge((line, col), (line, col - 1));
+                disjoint_set.insert((line, col), (1, 2));
+                disjoint_set.union(&(line, col), &(line, col - 1));
             }
             // XXX
             // AAA
             // XXX
             [[false, false, _], [true, true, true], [false, false, _]] => {
-                merge_insert.insert((line, col), (1, 0));
-                merge_insert.merge((line, col), (line, col - 1));
+                disjoint_set.insert((line, col), (1, 0));
+                disjoint_set.union(&(line, col), &(line, col - 1));
             }
             // AXX
             // AAA
             // XXX
             [[true, false, _], [true, true, true], [false, false, _]] => {
-                merge_insert.insert((line, col), (1, 1));
-                merge_insert.merge((line, col), (line, col - 1));
+                disjoint_set.insert((line, col), (1, 1));
+                disjoint_set.union(&(line, col), &(line, col - 1));
             }
             // XXX
             // AAA
             // AXX
             [[false, false, _], [true, true, true], [true, false, _]] => {
-                merge_insert.insert((line, col), (1, 1));
-                merge_insert.merge((line, col), (line, col - 1));
+                disjoint_set.insert((line, col), (1, 1));
+                disjoint_set.union(&(line, col), &(line, col - 1));
             }
             // AXX
             // AAA
             // AXX
             [[true, false, _], [true, true, true], [true, false, _]] => {
-                merge_insert.insert((line, col), (1, 2));
-                merge_insert.merge((line, col), (line, col - 1));
+                disjoint_set.insert((line, col), (1, 2));
+                disjoint_set.union(&(line, col), &(line, col - 1));
             }
             // XXX
             // XAA
             // XAX
             [[_, false, _], [false, true, true], [_, true, _]] => {
-                merge_insert.insert((line, col), (1, 2));
+                disjoint_set.insert((line, col), (1, 2));
             }
             // XAX
             // AAX
             // XAX
             [[_, true, false], [true, true, false], [_, true, _]] => {
-                merge_insert.insert((line, col), (1, 0));
-                merge_insert.merge((line, col), (line - 1, col));
-                merge_insert.merge((line, col), (line, col - 1));
+                disjoint_set.insert((line, col), (1, 0));
+                disjoint_set.union(&(line, col), &(line - 1, col));
+                disjoint_set.union(&(line, col), &(line, col - 1));
             }
             // XAA
             // AAX
             // XAX
             [[_, true, true], [true, true, false], [_, true, _]] => {
-                merge_insert.insert((line, col), (1, 1));
-                merge_insert.merge((line, col), (line - 1, col));
-                merge_insert.merge((line, col), (line, col - 1));
+                disjoint_set.insert((line, col), (1, 1));
+                disjoint_set.union(&(line, col), &(line - 1, col));
+                disjoint_set.union(&(line, col), &(line, col - 1));
             }
             // XAX
             // AAA
             // XXX
             [[_, true, _], [true, true, true], [false, false, _]] => {
-                merge_insert.insert((line, col), (1, 0));
-                merge_insert.merge((line, col), (line - 1, col));
-                merge_insert.merge((line, col), (line, col - 1));
+                disjoint_set.insert((line, col), (1, 0));
+                disjoint_set.union(&(line, col), &(line - 1, col));
+                disjoint_set.union(&(line, col), &(line, col - 1));
             }
             // XAX
             // AAA
             // AXX
             [[_, true, _], [true, true, true], [true, false, _]] => {
-                merge_insert.insert((line, col), (1, 1));
-                merge_insert.merge((line, col), (line - 1, col));
-                merge_insert.merge((line, col), (line, col - 1));
+                disjoint_set.insert((line, col), (1, 1));
+                disjoint_set.union(&(line, col), &(line - 1, col));
+                disjoint_set.union(&(line, col), &(line, col - 1));
             }
             // XAX
             // XAA
             // XAX
             [[false, true, _], [false, true, true], [_, true, _]] => {
-                merge_insert.insert((line, col), (1, 0));
-                merge_insert.merge((line, col), (line - 1, col));
+                disjoint_set.insert((line, col), (1, 0));
+                disjoint_set.union(&(line, col), &(line - 1, col));
             }
             // AAX
             // XAA
             // XAX
             [[true, true, _], [false, true, true], [_, true, _]] => {
-                merge_insert.insert((line, col), (1, 1));
-                merge_insert.merge((line, col), (line - 1, col));
+                disjoint_set.insert((line, col), (1, 1));
+                disjoint_set.union(&(line, col), &(line - 1, col));
             }
             // XXX
             // AAA
             // XAX
             [[false, false, _], [true, true, true], [_, true, _]] => {
-                merge_insert.insert((line, col), (1, 0));
-                merge_insert.merge((line, col), (line, col - 1));
+                disjoint_set.insert((line, col), (1, 0));
+                disjoint_set.union(&(line, col), &(line, col - 1));
             }
             // AXX
             // AAA
             // XAX
             [[true, false, _], [true, true, true], [_, true, _]] => {
-                merge_insert.insert((line, col), (1, 1));
-                merge_insert.merge((line, col), (line, col - 1));
+                disjoint_set.insert((line, col), (1, 1));
+                disjoint_set.union(&(line, col), &(line, col - 1));
             }
         };
     }
 
-    merge_insert
-        .drain()
-        .filter_map(|(_, v)| match v {
-            MergeInsertNode::Root(v) => Some(v.get()),
-            _ => None,
+    disjoint_set
+}
+
+fn find_regions(data: &[&[u8]]) -> Vec<Region> {
+    let mut sides = sides_disjoint_set(data);
+
+    let height = data.len();
+    let width = data.first().map_or(0, |line| line.len());
+
+    let mut plants = data.concat();
+    let labelling = label_components(
+        &Vec2d::new(&mut plants, width, height),
+        Connectivity::Four,
+        |lhs, rhs| lhs == rhs,
+    );
+    let labels = labelling
+        .labels()
+        .iter()
+        .map(|label| usize::try_from(*label).unwrap())
+        .collect::<Vec<_>>();
+
+    let mut regions = labelling
+        .components()
+        .iter()
+        .map(|component| {
+            let first = component.first;
+            let (_, sides) = *sides.value(&(first.row, first.column)).unwrap();
+            Region {
+                plant: data[first.row][first.column],
+                cells: BTreeSet::new(),
+                area: u64::try_from(component.area).unwrap(),
+                perimeter: u64::try_from(component.perimeter).unwrap(),
+                sides,
+                bounding_box: component.bounding_box,
+                holes: BTreeSet::new(),
+                adjacent: BTreeSet::new(),
+            }
+        })
+        .collect::<Vec<_>>();
+    for (index, label) in labels.iter().enumerate() {
+        regions[*label]
+            .cells
+            .insert(Coord::new(index / width, index % width));
+    }
+
+    for row in 0..height {
+        for column in 0..width {
+            let label = labels[row * width + column];
+            let neighbours = [
+                (column + 1 < width).then(|| labels[row * width + column + 1]),
+                (row + 1 < height).then(|| labels[(row + 1) * width + column]),
+            ];
+            for neighbour in neighbours.into_iter().flatten() {
+                if neighbour != label {
+                    regions[label].adjacent.insert(neighbour);
+                    regions[neighbour].adjacent.insert(label);
+                }
+            }
+        }
+    }
+
+    let holes = regions
+        .iter()
+        .enumerate()
+        .map(|(index, region)| find_holes(region, &labels, width, index))
+        .collect::<Vec<_>>();
+    for (region, holes) in regions.iter_mut().zip(holes) {
+        region.holes = holes;
+    }
+
+    regions
+}
+
+// Cells inside the bounding box of the region that can't reach its border
+// without crossing the region are enclosed by it
+fn find_holes(region: &Region, labels: &[usize], width: usize, index: usize) -> BTreeSet<usize> {
+    let (top_left, bottom_right) = region.bounding_box;
+    let bounds = Coord::new(bottom_right.row + 1, bottom_right.column + 1);
+
+    let mut reached = BTreeSet::new();
+    let mut stack = (top_left.row..=bottom_right.row)
+        .flat_map(|row| {
+            let columns = if row == top_left.row || row == bottom_right.row {
+                (top_left.column..=bottom_right.column).collect::<Vec<_>>()
+            } else {
+                vec![top_left.column, bottom_right.column]
+            };
+            columns
+                .into_iter()
+                .map(move |column| Coord::new(row, column))
         })
+        .filter(|coord| !region.cells.contains(coord))
+        .collect::<Vec<_>>();
+
+    while let Some(coord) = stack.pop() {
+        if !reached.insert(coord) {
+            continue;
+        }
+        stack.extend(
+            coord
+                .adjacent_4_way(bounds)
+                .into_iter()
+                .flatten()
+                .filter(|adjacent| {
+                    adjacent.row >= top_left.row
+                        && adjacent.column >= top_left.column
+                        && !region.cells.contains(adjacent)
+                        && !reached.contains(adjacent)
+                }),
+        );
+    }
+
+    (top_left.row..=bottom_right.row)
+        .flat_map(|row| (top_left.column..=bottom_right.column).map(move |column| (row, column)))
+        .map(|(row, column)| Coord::new(row, column))
+        .filter(|coord| !region.cells.contains(coord) && !reached.contains(coord))
+        .map(|coord| labels[coord.row * width + coord.column])
+        .filter(|label| *label != index)
         .collect()
 }
 
+pub fn regions(mut reader: impl Read) -> Vec<Region> {
+    let mut data = Vec::with_capacity(10_000);
+    reader.read_to_end(&mut data).unwrap();
+    let data = parse_data(data.as_slice());
+
+    find_regions(data.as_slice())
+}
+
 pub fn part1(mut reader: impl Read) -> u64 {
     let mut data = Vec::with_capacity(10_000);
     reader.read_to_end(&mut data).unwrap();
@@ -451,66 +598,3 @@ impl Iterator for Convolution<'_> {
         }
     }
 }
-
-#[derive(Debug, Clone)]
-enum MergeInsertNode {
-    Root(Rc<Cell<(u64, u64)>>),
-    Indirection(usize, usize),
-}
-
-#[derive(Debug, Default)]
-struct MergeInsert {
-    data: HashMap<(usize, usize), MergeInsertNode>,
-}
-
-impl MergeInsert {
-    fn insert(&mut self, index: (usize, usize), value: (u64, u64)) {
-        self.data
-            .insert(index, MergeInsertNode::Root(Rc::new(Cell::new(value))));
-    }
-
-    fn merge(&mut self, li: (usize, usize), ri: (usize, usize)) {
-        let Some(l) = self.data.get(&li).cloned() else {
-            unreachable!("Should never be called with invalid l.")
-        };
-        let Some(r) = self.data.get(&ri).cloned() else {
-            unreachable!("Should never be called with invalid r.")
-        };
-
-        match (l, r) {
-            (MergeInsertNode::Root(l), MergeInsertNode::Root(r)) => {
-                if !std::ptr::addr_eq(l.as_ptr(), r.as_ptr()) {
-                    let r_old = r.get();
-                    let l_old = l.get();
-                    r.set((r_old.0 + l_old.0, r_old.1 + l_old.1));
-                    self.data
-                        .insert(li, MergeInsertNode::Indirection(ri.0, ri.1));
-                }
-            }
-            (MergeInsertNode::Root(_), MergeInsertNode::Indirection(line, col)) => {
-                self.merge(li, (line, col));
-            }
-            (MergeInsertNode::Indirection(line, col), MergeInsertNode::Root(_)) => {
-                self.merge((line, col), ri);
-            }
-            (
-                MergeInsertNode::Indirection(lline, lcol),
-                MergeInsertNode::Indirection(rline, rcol),
-            ) => self.merge((lline, lcol), (rline, rcol)),
-        }
-    }
-}
-
-impl Deref for MergeInsert {
-    type Target = HashMap<(usize, usize), MergeInsertNode>;
-
-    fn deref(&self) -> &Self::Target {
-        &self.data
-    }
-}
-
-impl DerefMut for MergeInsert {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.data
-    }
-}
@@ -1,61 +1,84 @@
 use std::{
+    error::Error,
+    fmt::Display,
     io::{BufRead, BufReader, Read},
     ops::RangeInclusive,
 };
 
 fn parse_input(reader: impl Read, padding: isize, range: RangeInclusive<isize>) -> Vec<Machine> {
-    let mut buf = BufReader::with_capacity(10_000, reader).lines();
+    parse_claw_machines(reader, padding, range)
+        .into_iter()
+        .map(|machine| {
+            assert_eq!(machine.buttons.len(), 2, "Should have buttons A and B.");
+            assert_eq!(machine.prize.len(), 2, "Should have axes X and Y.");
+            Machine::new(
+                (machine.buttons[0][0], machine.buttons[0][1]),
+                (machine.buttons[1][0], machine.buttons[1][1]),
+                (machine.prize[0], machine.prize[1]),
+                machine.range,
+            )
+        })
+        .collect()
+}
+
+// Machines with any number of `Button <name>` lines followed by a `Prize`
+// line, all using the same axes in the same order
+pub fn parse_claw_machines(
+    reader: impl Read,
+    padding: isize,
+    range: RangeInclusive<isize>,
+) -> Vec<ClawMachine> {
     let mut machines = Vec::with_capacity(50);
+    let mut buttons = Vec::new();
+    let mut axes = Vec::new();
 
-    loop {
-        let button_a = parse_button(
-            &buf.next().expect("Should have button a.").unwrap(),
-            "Button A",
-            '+',
-        );
-        let button_b = parse_button(
-            &buf.next().expect("Should have button a.").unwrap(),
-            "Button B",
-            '+',
-        );
-        let prize = parse_button(
-            &buf.next().expect("Should have button a.").unwrap(),
-            "Prize",
-            '=',
-        );
-        machines.push(Machine {
-            button_a,
-            button_b,
-            prize: (prize.0 + padding, prize.1 + padding),
-            range: range.clone(),
-        });
-        if buf.next().is_none() {
-            break;
+    for line in BufReader::with_capacity(10_000, reader).lines() {
+        let line = line.unwrap();
+        let Some((header, values)) = line.split_once(':') else {
+            assert!(line.trim().is_empty(), "Unexpected line {line:?}.");
+            continue;
+        };
+        let (line_axes, values): (Vec<_>, Vec<_>) = if header.starts_with("Button ") {
+            parse_values(values, '+').unzip()
+        } else {
+            assert_eq!(header, "Prize");
+            parse_values(values, '=').unzip()
+        };
+
+        if axes.is_empty() {
+            axes = line_axes;
+        } else {
+            assert_eq!(axes, line_axes, "All lines should have the same axes.");
+        }
+
+        if header == "Prize" {
+            machines.push(ClawMachine::new(
+                std::mem::take(&mut buttons),
+                values.into_iter().map(|value| value + padding).collect(),
+                range.clone(),
+            ));
+            axes.clear();
+        } else {
+            buttons.push(values);
         }
     }
+    assert!(buttons.is_empty(), "Buttons without prize.");
 
     machines
 }
 
-fn parse_button(line: &str, header: &str, operator: char) -> (isize, isize) {
-    line.split_once(':')
-        .and_then(|(left, right)| {
-            assert_eq!(left, header);
-
-            right.split_once(',').map(|(x, y)| {
-                (
-                    x.trim()
-                        .trim_start_matches(&format!("X{operator}"))
-                        .parse::<isize>()
-                        .unwrap(),
-                    y.trim()
-                        .trim_start_matches(&format!("Y{operator}"))
-                        .parse::<isize>()
-                        .unwrap(),
-                )
-            })
-        })
-        .unwrap()
+fn parse_values(values: &str, operator: char) -> impl Iterator<Item = (String, isize)> + '_ {
+    values.split(',').map(move |value| {
+        let value = value.trim();
+        let axis_len = value
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .expect("Should have a value.");
+        let (axis, value) = value.split_at(axis_len);
+        (
+            axis.to_owned(),
+            value.trim_start_matches(operator).parse::<isize>().unwrap(),
+        )
+    })
 }
 
 pub fn part1(reader: impl Read) -> isize {
@@ -76,27 +99,684 @@ pub fn part2(reader: impl Read) -> isize {
         .sum()
 }
 
-#[derive(Debug)]
-struct Machine {
-    button_a: (isize, isize),
-    button_b: (isize, isize),
-    prize: (isize, isize),
-    range: RangeInclusive<isize>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Solution {
+    pub presses_a: isize,
+    pub presses_b: isize,
+    pub cost: isize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Machine {
+    pub button_a: (isize, isize),
+    pub button_b: (isize, isize),
+    pub prize: (isize, isize),
+    // Allowed number of presses of each button
+    pub range: RangeInclusive<isize>,
+    // Tokens per press of button A and B
+    pub costs: (isize, isize),
 }
 
 impl Machine {
+    pub fn new(
+        button_a: (isize, isize),
+        button_b: (isize, isize),
+        prize: (isize, isize),
+        range: RangeInclusive<isize>,
+    ) -> Self {
+        Self {
+            button_a,
+            button_b,
+            prize,
+            range,
+            costs: (3, 1),
+        }
+    }
+
+    pub fn with_costs(self, cost_a: isize, cost_b: isize) -> Self {
+        Self {
+            costs: (cost_a, cost_b),
+            ..self
+        }
+    }
+
     fn find_cheapest_solution(&self) -> Option<isize> {
-        let b = (self.prize.0 * self.button_a.1 - self.prize.1 * self.button_a.0)
-            / (self.button_b.0 * self.button_a.1 - self.button_a.0 * self.button_b.1);
-        let a = (self.prize.1 - b * self.button_b.1) / self.button_a.1;
-        if self.prize.0 == a * self.button_a.0 + b * self.button_b.0
-            && self.prize.1 == a * self.button_a.1 + b * self.button_b.1
-            && self.range.contains(&a)
-            && self.range.contains(&b)
-        {
-            Some(a * 3 + b)
+        self.solve().map(|solution| solution.cost)
+    }
+
+    // Everything is computed on i128 so that no intermediate product can
+    // overflow and every division is checked to be exact
+    pub fn solve(&self) -> Option<Solution> {
+        let (ax, ay) = (wide(self.button_a.0), wide(self.button_a.1));
+        let (bx, by) = (wide(self.button_b.0), wide(self.button_b.1));
+        let (px, py) = (wide(self.prize.0), wide(self.prize.1));
+        let range = (wide(*self.range.start()), wide(*self.range.end()));
+        if range.0 > range.1 {
+            return None;
+        }
+
+        let determinant = ax * by - ay * bx;
+        let (a, b) = if determinant != 0 {
+            let a = px * by - py * bx;
+            let b = ax * py - ay * px;
+            if a % determinant != 0 || b % determinant != 0 {
+                return None;
+            }
+            let (a, b) = (a / determinant, b / determinant);
+            if !(range.0..=range.1).contains(&a) || !(range.0..=range.1).contains(&b) {
+                return None;
+            }
+            (a, b)
+        } else {
+            self.solve_collinear(range)?
+        };
+
+        Some(Solution {
+            presses_a: isize::try_from(a).ok()?,
+            presses_b: isize::try_from(b).ok()?,
+            cost: isize::try_from(a * wide(self.costs.0) + b * wide(self.costs.1)).ok()?,
+        })
+    }
+
+    // Both buttons move the claw along the same line, the prize must be on it
+    // too. The problem is then the single equation `a * x + b * y = p`, which
+    // has a line of solutions `(a0 + k * y', b0 - k * x')`, and as the cost is
+    // linear on `k` the cheapest one is at one of the ends of the range of `k`.
+    fn solve_collinear(&self, range: (i128, i128)) -> Option<(i128, i128)> {
+        let (ax, ay) = (wide(self.button_a.0), wide(self.button_a.1));
+        let (bx, by) = (wide(self.button_b.0), wide(self.button_b.1));
+        let (px, py) = (wide(self.prize.0), wide(self.prize.1));
+        let (cost_a, cost_b) = (wide(self.costs.0), wide(self.costs.1));
+
+        if ax * py - ay * px != 0 || bx * py - by * px != 0 {
+            return None;
+        }
+
+        // Any coordinate where a button moves the claw gives the equation
+        let (x, y, p) = if ax != 0 || bx != 0 {
+            (ax, bx, px)
+        } else if ay != 0 || by != 0 {
+            (ay, by, py)
+        } else if px == 0 && py == 0 {
+            // Neither button moves the claw, which is already on the prize
+            let cheapest = |cost: i128| if cost < 0 { range.1 } else { range.0 };
+            return Some((cheapest(cost_a), cheapest(cost_b)));
         } else {
-            None
+            return None;
+        };
+
+        let (gcd, s, t) = extended_gcd(x, y);
+        if p % gcd != 0 {
+            return None;
+        }
+        let (a0, b0) = (s * (p / gcd), t * (p / gcd));
+        let (step_a, step_b) = (y / gcd, -x / gcd);
+
+        let (low, high) = steps_in_range(a0, step_a, range)?;
+        let (low, high) = steps_in_range(b0, step_b, range)
+            .map(|(b_low, b_high)| (low.max(b_low), high.min(b_high)))
+            .filter(|(low, high)| low <= high)?;
+
+        // The number of presses of A is increasing with `k` when `step_a` is
+        // positive, ties are broken by pressing A the least
+        let slope = cost_a * step_a + cost_b * step_b;
+        let k = match slope.cmp(&0) {
+            std::cmp::Ordering::Greater => low,
+            std::cmp::Ordering::Less => high,
+            std::cmp::Ordering::Equal if step_a > 0 => low,
+            std::cmp::Ordering::Equal => high,
+        };
+
+        Some((a0 + k * step_a, b0 + k * step_b))
+    }
+}
+
+fn wide(value: isize) -> i128 {
+    i128::try_from(value).unwrap()
+}
+
+// `(gcd, s, t)` with `s * x + t * y == gcd` and `gcd` positive, `x` and `y`
+// can't both be zero
+fn extended_gcd(x: i128, y: i128) -> (i128, i128, i128) {
+    let (mut old_r, mut r) = (x, y);
+    let (mut old_s, mut s) = (1, 0);
+    let (mut old_t, mut t) = (0, 1);
+    while r != 0 {
+        let quotient = old_r / r;
+        (old_r, r) = (r, old_r - quotient * r);
+        (old_s, s) = (s, old_s - quotient * s);
+        (old_t, t) = (t, old_t - quotient * t);
+    }
+    if old_r < 0 {
+        (-old_r, -old_s, -old_t)
+    } else {
+        (old_r, old_s, old_t)
+    }
+}
+
+// Range of `k` for which `base + k * step` is within `range`, unbounded when
+// `step` is zero
+fn steps_in_range(base: i128, step: i128, range: (i128, i128)) -> Option<(i128, i128)> {
+    if step == 0 {
+        return (range.0 <= base && base <= range.1).then_some((i128::MIN, i128::MAX));
+    }
+    let (low, high) = if step > 0 {
+        (
+            div_ceil(range.0 - base, step),
+            div_floor(range.1 - base, step),
+        )
+    } else {
+        (
+            div_ceil(range.1 - base, step),
+            div_floor(range.0 - base, step),
+        )
+    };
+    (low <= high).then_some((low, high))
+}
+
+fn div_floor(lhs: i128, rhs: i128) -> i128 {
+    let quotient = lhs / rhs;
+    if lhs % rhs != 0 && (lhs < 0) != (rhs < 0) {
+        quotient - 1
+    } else {
+        quotient
+    }
+}
+
+fn div_ceil(lhs: i128, rhs: i128) -> i128 {
+    let quotient = lhs / rhs;
+    if lhs % rhs != 0 && (lhs < 0) == (rhs < 0) {
+        quotient + 1
+    } else {
+        quotient
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClawSolution {
+    pub presses: Vec<isize>,
+    pub cost: isize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClawError {
+    // The search over the free presses went through more nodes than allowed
+    SearchLimit { nodes: usize },
+}
+
+impl Display for ClawError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SearchLimit { nodes } => {
+                write!(f, "Search for the cheapest presses went over {nodes} nodes")
+            }
+        }
+    }
+}
+
+impl Error for ClawError {}
+
+#[derive(Debug, Clone)]
+pub struct ClawMachine {
+    // Movement of each button along every axis
+    pub buttons: Vec<Vec<isize>>,
+    pub prize: Vec<isize>,
+    // Allowed number of presses of each button, negative counts are never
+    // allowed
+    pub range: RangeInclusive<isize>,
+    // Tokens per press of each button
+    pub costs: Vec<isize>,
+    // Nodes the search over the free presses may go through
+    pub search_limit: usize,
+}
+
+impl ClawMachine {
+    // The first button costs 3 tokens and every other one 1, as A and B do
+    pub fn new(buttons: Vec<Vec<isize>>, prize: Vec<isize>, range: RangeInclusive<isize>) -> Self {
+        let costs = (0..buttons.len())
+            .map(|button| if button == 0 { 3 } else { 1 })
+            .collect();
+        Self {
+            buttons,
+            prize,
+            range,
+            costs,
+            search_limit: 1 << 20,
+        }
+    }
+
+    pub fn with_costs(self, costs: Vec<isize>) -> Self {
+        assert_eq!(costs.len(), self.buttons.len());
+        Self { costs, ..self }
+    }
+
+    pub fn with_search_limit(self, search_limit: usize) -> Self {
+        Self {
+            search_limit,
+            ..self
+        }
+    }
+
+    // Eliminates the system to `pivot * x_p + sum(coefficient * x_free) = rhs`
+    // rows, then searches the free presses, tightening their bounds with the
+    // ones of the pivot presses. The last free press is solved directly, the
+    // other ones are branched on from the cheapest end and cut as soon as the
+    // cost, relaxed to any presses within the bounds, can't beat the best
+    // found. That relaxation can still leave too many presses to go through,
+    // so the search gives up after `search_limit` nodes.
+    pub fn solve(&self) -> Result<Option<ClawSolution>, ClawError> {
+        let buttons = self.buttons.len();
+        let low = wide((*self.range.start()).max(0));
+        let high = wide(*self.range.end());
+        if low > high {
+            return Ok(None);
+        }
+        assert!(self
+            .buttons
+            .iter()
+            .all(|button| button.len() == self.prize.len()));
+
+        let mut rows = self
+            .prize
+            .iter()
+            .enumerate()
+            .map(|(axis, prize)| {
+                self.buttons
+                    .iter()
+                    .map(|button| wide(button[axis]))
+                    .chain([wide(*prize)])
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut pivots = Vec::new();
+        for column in 0..buttons {
+            let Some(pivot_row) = (pivots.len()..rows.len()).find(|row| rows[*row][column] != 0)
+            else {
+                continue;
+            };
+            rows.swap(pivots.len(), pivot_row);
+            let pivot_row = pivots.len();
+            if rows[pivot_row][column] < 0 {
+                rows[pivot_row]
+                    .iter_mut()
+                    .for_each(|value| *value = -*value);
+            }
+            for row in 0..rows.len() {
+                let factor = rows[row][column];
+                if row == pivot_row || factor == 0 {
+                    continue;
+                }
+                let pivot = rows[pivot_row][column];
+                let pivot_values = rows[pivot_row].clone();
+                for (value, pivot_value) in rows[row].iter_mut().zip(pivot_values) {
+                    *value = *value * pivot - pivot_value * factor;
+                }
+                reduce(&mut rows[row]);
+            }
+            pivots.push(column);
+        }
+
+        // Rows left are all zeros on the buttons
+        if rows[pivots.len()..].iter().any(|row| row[buttons] != 0) {
+            return Ok(None);
+        }
+        rows.truncate(pivots.len());
+
+        let free = (0..buttons)
+            .filter(|button| !pivots.contains(button))
+            .collect::<Vec<_>>();
+
+        // Substituting the pivot presses, `scale * cost` is `base` plus the
+        // reduced cost of every free press
+        let scale = rows.iter().zip(&pivots).fold(1, |scale, (row, pivot)| {
+            scale / extended_gcd(scale, row[*pivot]).0 * row[*pivot]
+        });
+        let mut reduced = vec![0; buttons];
+        let mut base = 0;
+        for (row, pivot) in rows.iter().zip(&pivots) {
+            let weight = wide(self.costs[*pivot]) * (scale / row[*pivot]);
+            base += weight * row[buttons];
+            for button in &free {
+                reduced[*button] -= weight * row[*button];
+            }
+        }
+        for button in &free {
+            reduced[*button] += wide(self.costs[*button]) * scale;
+        }
+
+        let mut search = ClawSearch {
+            rows: &rows,
+            pivots: &pivots,
+            free: &free,
+            costs: &self.costs,
+            reduced: &reduced,
+            base,
+            scale,
+            bounds: (low, high),
+            presses: vec![0; buttons],
+            best: None,
+            nodes: 0,
+            node_limit: self.search_limit,
+        };
+        search.search(0);
+        if search.nodes > search.node_limit {
+            return Err(ClawError::SearchLimit {
+                nodes: search.node_limit,
+            });
+        }
+
+        let Some((cost, presses)) = search.best else {
+            return Ok(None);
+        };
+        Ok(presses
+            .into_iter()
+            .map(|presses| isize::try_from(presses).ok())
+            .collect::<Option<_>>()
+            .zip(isize::try_from(cost).ok())
+            .map(|(presses, cost)| ClawSolution { presses, cost }))
+    }
+}
+
+fn reduce(row: &mut [i128]) {
+    let gcd = row
+        .iter()
+        .fold(0, |gcd, value| extended_gcd(gcd, *value).0.max(0));
+    if gcd > 1 {
+        row.iter_mut().for_each(|value| *value /= gcd);
+    }
+}
+
+// Solutions of `coefficient * x ≡ rest (mod modulus)`, as `x ≡ residue (mod
+// step)`
+fn solve_congruence(coefficient: i128, rest: i128, modulus: i128) -> Option<(i128, i128)> {
+    let (gcd, s, _) = extended_gcd(coefficient, modulus);
+    if rest % gcd != 0 {
+        return None;
+    }
+    let step = modulus / gcd;
+    Some((
+        ((rest / gcd).rem_euclid(step) * s.rem_euclid(step)).rem_euclid(step),
+        step,
+    ))
+}
+
+// Solutions of both congruences `x ≡ residue (mod step)`
+fn combine_congruences(lhs: (i128, i128), rhs: (i128, i128)) -> Option<(i128, i128)> {
+    let (k, step) = solve_congruence(lhs.1, rhs.0 - lhs.0, rhs.1)?;
+    let step = lhs.1 * step;
+    Some(((lhs.0 + lhs.1 * k).rem_euclid(step), step))
+}
+
+struct ClawSearch<'a> {
+    rows: &'a [Vec<i128>],
+    pivots: &'a [usize],
+    free: &'a [usize],
+    costs: &'a [isize],
+    reduced: &'a [i128],
+    base: i128,
+    scale: i128,
+    bounds: (i128, i128),
+    presses: Vec<i128>,
+    best: Option<(i128, Vec<i128>)>,
+    nodes: usize,
+    node_limit: usize,
+}
+
+impl ClawSearch<'_> {
+    fn search(&mut self, depth: usize) {
+        self.nodes += 1;
+        if self.nodes > self.node_limit {
+            return;
+        }
+        let (low, high) = self.bounds;
+        let Some(&button) = self.free.get(depth) else {
+            self.solve_pivots();
+            return;
+        };
+
+        // Bounds of the button so that every pivot press can stay within the
+        // bounds, assuming the free presses not chosen yet can take any value
+        let mut bounds = (low, high);
+        let mut constrained = false;
+        for (row, pivot) in self.rows.iter().zip(self.pivots) {
+            let coefficient = row[button];
+            if coefficient == 0 {
+                continue;
+            }
+            constrained = true;
+            let pivot = row[*pivot];
+            let mut rest = (row[row.len() - 1], row[row.len() - 1]);
+            for (index, free) in self.free.iter().enumerate() {
+                let value = row[*free];
+                if index < depth {
+                    rest.0 -= value * self.presses[*free];
+                    rest.1 -= value * self.presses[*free];
+                } else if index > depth {
+                    let (min, max) = if value > 0 {
+                        (value * low, value * high)
+                    } else {
+                        (value * high, value * low)
+                    };
+                    rest.0 -= max;
+                    rest.1 -= min;
+                }
+            }
+            // pivot * low <= rest - coefficient * x <= pivot * high
+            let (min, max) = (rest.0 - pivot * high, rest.1 - pivot * low);
+            let (min, max) = if coefficient > 0 {
+                (div_ceil(min, coefficient), div_floor(max, coefficient))
+            } else {
+                (div_ceil(max, coefficient), div_floor(min, coefficient))
+            };
+            bounds = (bounds.0.max(min), bounds.1.min(max));
+        }
+
+        // A button that doesn't move the claw only changes the cost
+        if !constrained {
+            bounds = if self.costs[button] < 0 {
+                (high, high)
+            } else {
+                (low, low)
+            };
+        }
+
+        if depth + 1 < self.free.len() {
+            self.branch(depth, bounds);
+            return;
+        }
+
+        // The last free press sets every pivot press. They are integers when
+        // it is in an arithmetic progression, and as the cost is linear on
+        // it the cheapest is at one of the ends of the progression.
+        let Some((residue, step)) =
+            self.rows
+                .iter()
+                .zip(self.pivots)
+                .try_fold((0, 1), |congruence, (row, pivot)| {
+                    let rest = self.rest(row, depth);
+                    solve_congruence(row[button], rest, row[*pivot])
+                        .and_then(|row_congruence| combine_congruences(congruence, row_congruence))
+                })
+        else {
+            return;
+        };
+        let first = bounds.0 + (residue - bounds.0).rem_euclid(step);
+        let last = bounds.1 - (bounds.1 - residue).rem_euclid(step);
+        if first > last {
+            return;
+        }
+        for presses in [first, last] {
+            self.presses[button] = presses;
+            self.solve_pivots();
+        }
+    }
+
+    // Goes through the presses of the free button at `depth` from the one
+    // with the cheapest relaxation, in both directions until the relaxation
+    // can't beat the best cost found. The relaxation is convex on the
+    // presses, so nothing cheaper is left past that point.
+    fn branch(&mut self, depth: usize, bounds: (i128, i128)) {
+        let button = self.free[depth];
+        let relaxation = self.relaxation(depth);
+        let mut bounds = bounds;
+        for (presses, cost, rhs) in &relaxation {
+            match (presses.signum(), cost) {
+                (1, 0) => bounds.1 = bounds.1.min(div_floor(*rhs, *presses)),
+                (-1, 0) => bounds.0 = bounds.0.max(div_ceil(*rhs, *presses)),
+                (0, 0) if *rhs < 0 => return,
+                _ => (),
+            }
+        }
+        if bounds.0 > bounds.1 {
+            return;
+        }
+
+        // Cheapest `scale * cost` as a fraction when pressing `presses` times
+        let cheapest = |presses: i128| {
+            relaxation
+                .iter()
+                .filter(|(_, cost, _)| *cost < 0)
+                .map(|(coefficient, cost, rhs)| (coefficient * presses - rhs, -cost))
+                .max_by(|lhs, rhs| (lhs.0 * rhs.1).cmp(&(rhs.0 * lhs.1)))
+                .unwrap()
+        };
+        let (mut first, mut last) = bounds;
+        while first < last {
+            let middle = div_floor(first + last, 2);
+            let (lhs, rhs) = (cheapest(middle), cheapest(middle + 1));
+            if rhs.0 * lhs.1 >= lhs.0 * rhs.1 {
+                last = middle;
+            } else {
+                first = middle + 1;
+            }
+        }
+
+        for (start, step) in [(first, -1), (first + 1, 1)] {
+            let mut presses = start;
+            while (bounds.0..=bounds.1).contains(&presses) {
+                let (cost, scale) = cheapest(presses);
+                if self
+                    .best
+                    .as_ref()
+                    .is_some_and(|(best, _)| cost > (best - 1) * self.scale * scale)
+                    || self.nodes > self.node_limit
+                {
+                    break;
+                }
+                self.presses[button] = presses;
+                self.search(depth + 1);
+                presses += step;
+            }
+        }
+    }
+
+    // Once the first `depth` free presses are chosen, the constraints left on
+    // the presses of the free button at `depth` and on `scale * cost`, as
+    // `presses * x + cost * y <= rhs` with `cost <= 0`. Every later free press
+    // is eliminated with Fourier-Motzkin, so they are exact for any real
+    // presses within the bounds.
+    fn relaxation(&self, depth: usize) -> Vec<(i128, i128, i128)> {
+        let (low, high) = self.bounds;
+        let free = &self.free[depth..];
+        let width = free.len() + 2;
+
+        let fixed = self.free[..depth].iter().fold(self.base, |fixed, free| {
+            fixed + self.reduced[*free] * self.presses[*free]
+        });
+        let mut constraints = vec![free
+            .iter()
+            .map(|free| self.reduced[*free])
+            .chain([-1, -fixed])
+            .collect::<Vec<_>>()];
+        for index in 0..free.len() {
+            let mut constraint = vec![0; width];
+            constraint[index] = 1;
+            constraint[width - 1] = high;
+            constraints.push(constraint.clone());
+            constraint[index] = -1;
+            constraint[width - 1] = -low;
+            constraints.push(constraint);
+        }
+        for (row, pivot) in self.rows.iter().zip(self.pivots) {
+            let rest = self.rest(row, depth);
+            let coefficients = free.iter().map(|free| row[*free]);
+            constraints.push(
+                coefficients
+                    .clone()
+                    .chain([0, rest - row[*pivot] * low])
+                    .collect(),
+            );
+            constraints.push(
+                coefficients
+                    .map(|coefficient| -coefficient)
+                    .chain([0, row[*pivot] * high - rest])
+                    .collect(),
+            );
+        }
+
+        for column in 1..free.len() {
+            let (kept, eliminated): (Vec<_>, Vec<_>) = constraints
+                .into_iter()
+                .partition(|constraint| constraint[column] == 0);
+            constraints = kept;
+            for upper in eliminated.iter().filter(|upper| upper[column] > 0) {
+                for lower in eliminated.iter().filter(|lower| lower[column] < 0) {
+                    let mut constraint = upper
+                        .iter()
+                        .zip(lower)
+                        .map(|(upper_value, lower_value)| {
+                            upper_value * -lower[column] + lower_value * upper[column]
+                        })
+                        .collect::<Vec<_>>();
+                    reduce(&mut constraint);
+                    constraints.push(constraint);
+                }
+            }
+            constraints.sort_unstable();
+            constraints.dedup();
+        }
+
+        constraints
+            .into_iter()
+            .map(|constraint| (constraint[0], constraint[width - 2], constraint[width - 1]))
+            .collect()
+    }
+
+    // Right hand side of `row` once the first `depth` free presses are chosen
+    fn rest(&self, row: &[i128], depth: usize) -> i128 {
+        self.free[..depth]
+            .iter()
+            .fold(row[row.len() - 1], |rest, free| {
+                rest - row[*free] * self.presses[*free]
+            })
+    }
+
+    fn solve_pivots(&mut self) {
+        let (low, high) = self.bounds;
+        for (row, pivot) in self.rows.iter().zip(self.pivots) {
+            let mut rest = row[row.len() - 1];
+            for free in self.free {
+                rest -= row[*free] * self.presses[*free];
+            }
+            if rest % row[*pivot] != 0 {
+                return;
+            }
+            let presses = rest / row[*pivot];
+            if presses < low || presses > high {
+                return;
+            }
+            self.presses[*pivot] = presses;
+        }
+
+        let cost = self
+            .presses
+            .iter()
+            .zip(self.costs)
+            .map(|(presses, cost)| presses * wide(*cost))
+            .sum::<i128>();
+        if self.best.as_ref().is_none_or(|(best, _)| cost < *best) {
+            self.best = Some((cost, self.presses.clone()));
         }
     }
 }
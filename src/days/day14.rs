@@ -1,7 +1,7 @@
 use std::{
-    borrow::Borrow,
     cmp::Ordering,
-    io::{BufRead, BufReader, Read},
+    collections::{HashMap, HashSet},
+    io::{BufRead, BufReader, Read, Write},
 };
 
 pub fn part1_for_testing(reader: impl Read) -> isize {
@@ -13,14 +13,7 @@ pub fn part1(reader: impl Read) -> isize {
 }
 
 fn part1_internal(reader: impl Read, bounds: (i64, i64)) -> isize {
-    let quadrants = parse_input(reader, bounds)
-        .map(|mut robot| {
-            robot.step(100);
-            robot
-        })
-        .fold((0, 0, 0, 0), |a, b| count_robots_in_quadrant(a, b, bounds));
-
-    quadrants.0 * quadrants.1 * quadrants.2 * quadrants.3
+    Simulation::parse(reader, bounds).safety_factor(100)
 }
 
 pub fn part2_for_testing(reader: impl Read) -> i64 {
@@ -32,25 +25,275 @@ pub fn part2(reader: impl Read) -> i64 {
 }
 
 fn part2_internal(reader: impl Read, bounds: (i64, i64)) -> i64 {
-    let mut robots = parse_input(reader, bounds).collect::<Vec<_>>();
-    (1..=(bounds.0 * bounds.1))
-        .map(|i| {
-            robots.iter_mut().for_each(|robot| robot.step(1));
-            let quadrants = robots
-                .iter()
-                .fold((0, 0, 0, 0), |a, b| count_robots_in_quadrant(a, b, bounds));
-            (
-                i,
-                quadrants
+    let simulation = Simulation::parse(reader, bounds);
+    let period = simulation.period();
+    match simulation.crt_time() {
+        Some(0) | None => period,
+        Some(time) => time,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub time: i64,
+    pub width: usize,
+    pub height: usize,
+    // Number of robots on each cell, row major
+    pub counts: Vec<u32>,
+}
+
+impl Frame {
+    pub fn count(&self, x: usize, y: usize) -> u32 {
+        self.counts[y * self.width + x]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[u32]> {
+        self.counts.chunks(self.width)
+    }
+
+    // Longest run of occupied cells on a single row
+    pub fn longest_horizontal_run(&self) -> usize {
+        self.rows()
+            .map(|row| {
+                row.iter()
+                    .fold((0, 0), |(longest, current), count| {
+                        let current = if *count > 0 { current + 1 } else { 0 };
+                        (longest.max(current), current)
+                    })
                     .0
-                    .max(quadrants.1)
-                    .max(quadrants.2)
-                    .max(quadrants.3),
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    // Same drawing as the puzzle, the number of robots or `.`
+    pub fn render(&self) -> String {
+        self.rows()
+            .map(|row| {
+                row.iter()
+                    .map(|count| match count {
+                        0 => '.',
+                        1..=9 => char::from_digit(*count, 10).unwrap(),
+                        _ => '+',
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // Plain PBM, occupied cells are black
+    pub fn write_pbm(&self, mut writer: impl Write) -> std::io::Result<()> {
+        writeln!(writer, "P1")?;
+        writeln!(writer, "# t={}", self.time)?;
+        writeln!(writer, "{} {}", self.width, self.height)?;
+        for row in self.rows() {
+            let row = row
+                .iter()
+                .map(|count| if *count > 0 { "1" } else { "0" })
+                .collect::<Vec<_>>()
+                .join(" ");
+            writeln!(writer, "{row}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    robots: Vec<Robot>,
+    bounds: (i64, i64),
+}
+
+impl Simulation {
+    pub fn parse(reader: impl Read, bounds: (i64, i64)) -> Self {
+        Self {
+            robots: parse_input(reader, bounds).collect(),
+            bounds,
+        }
+    }
+
+    // Every frame repeats after this many seconds
+    pub fn period(&self) -> i64 {
+        lcm(self.bounds.0, self.bounds.1)
+    }
+
+    pub fn frame(&self, time: i64) -> Frame {
+        let width = usize::try_from(self.bounds.0).unwrap();
+        let height = usize::try_from(self.bounds.1).unwrap();
+        let mut counts = vec![0; width * height];
+        for robot in &self.robots {
+            let (x, y) = robot.position_at(time);
+            counts[usize::try_from(y).unwrap() * width + usize::try_from(x).unwrap()] += 1;
+        }
+        Frame {
+            time,
+            width,
+            height,
+            counts,
+        }
+    }
+
+    pub fn robots(&self) -> &[Robot] {
+        &self.robots
+    }
+
+    // Product of the number of robots in each quadrant after `time` seconds
+    pub fn safety_factor(&self, time: i64) -> isize {
+        let quadrants = self.robots.iter().fold((0, 0, 0, 0), |quadrants, robot| {
+            count_robots_in_quadrant(quadrants, robot.position_at(time), self.bounds)
+        });
+
+        quadrants.0 * quadrants.1 * quadrants.2 * quadrants.3
+    }
+
+    // First time, within the period, at which no two robots share a cell.
+    // The times at which two robots share a value on an axis are a linear
+    // congruence modulo the bound of the axis, so the times they share a cell
+    // combine both axes with CRT. Only the times are then checked against
+    // those classes, without placing any robot.
+    pub fn distinct_cells_time(&self) -> Option<i64> {
+        let meetings = |lhs: (i64, i64), rhs: (i64, i64), bound| {
+            solve_congruence(
+                (lhs.1 - rhs.1).rem_euclid(bound),
+                (rhs.0 - lhs.0).rem_euclid(bound),
+                bound,
             )
+        };
+
+        // Residues of the times sharing a cell, by modulus
+        let mut shared = HashMap::<i64, HashSet<i64>>::new();
+        for (index, lhs) in self.robots.iter().enumerate() {
+            for rhs in &self.robots[index + 1..] {
+                let Some((x, y)) = meetings(
+                    (lhs.position.0, lhs.velocity.0),
+                    (rhs.position.0, rhs.velocity.0),
+                    self.bounds.0,
+                )
+                .zip(meetings(
+                    (lhs.position.1, lhs.velocity.1),
+                    (rhs.position.1, rhs.velocity.1),
+                    self.bounds.1,
+                )) else {
+                    continue;
+                };
+                let Some(time) = chinese_remainder(x, y) else {
+                    continue;
+                };
+                let modulus = lcm(x.1, y.1);
+                if modulus == 1 {
+                    return None;
+                }
+                shared.entry(modulus).or_default().insert(time);
+            }
+        }
+
+        (0..self.period()).find(|time| {
+            shared
+                .iter()
+                .all(|(modulus, times)| !times.contains(&(time % modulus)))
         })
-        .max_by_key(|(_, score)| *score)
-        .map(|(i, _)| i)
-        .unwrap()
+    }
+
+    // Frames from time 0 up to the period, excluded
+    pub fn frames(&self) -> impl Iterator<Item = Frame> + '_ {
+        (0..self.period()).map(|time| self.frame(time))
+    }
+
+    // Robots on an axis come back to the same place every `bound` seconds, so
+    // the time with the smallest variance on the axis is searched within
+    // `0..bound`. Returns the times for X and Y.
+    pub fn min_variance_times(&self) -> (i64, i64) {
+        let axis = |select: fn(&(i64, i64)) -> i64, bound| {
+            (0..bound)
+                .min_by_key(|time| {
+                    let values = self.robots.iter().map(|robot| {
+                        i128::from(axis_at(
+                            select(&robot.position),
+                            select(&robot.velocity),
+                            bound,
+                            *time,
+                        ))
+                    });
+                    // Variance times the square of the number of robots
+                    let (count, sum, sum_squares) = values.fold((0, 0, 0), |acc, value| {
+                        (acc.0 + 1, acc.1 + value, acc.2 + value * value)
+                    });
+                    count * sum_squares - sum * sum
+                })
+                .unwrap_or(0)
+        };
+
+        (
+            axis(|pair| pair.0, self.bounds.0),
+            axis(|pair| pair.1, self.bounds.1),
+        )
+    }
+
+    // The time, within the period, matching the lowest variance of both axes.
+    // `None` if the times can't be combined, which can only happen if the
+    // bounds aren't coprime.
+    pub fn crt_time(&self) -> Option<i64> {
+        let (x, y) = self.min_variance_times();
+        chinese_remainder((x, self.bounds.0), (y, self.bounds.1))
+    }
+
+    // First time with at least `length` robots side by side on a row
+    pub fn horizontal_run_time(&self, length: usize) -> Option<i64> {
+        self.frames()
+            .find(|frame| frame.longest_horizontal_run() >= length)
+            .map(|frame| frame.time)
+    }
+}
+
+// Reduces the velocity and the time first so that any time can be used
+// without overflowing
+fn axis_at(position: i64, velocity: i64, bound: i64, time: i64) -> i64 {
+    (position + velocity.rem_euclid(bound) * time.rem_euclid(bound)).rem_euclid(bound)
+}
+
+fn gcd(lhs: i64, rhs: i64) -> i64 {
+    if rhs == 0 {
+        lhs.abs()
+    } else {
+        gcd(rhs, lhs % rhs)
+    }
+}
+
+fn lcm(lhs: i64, rhs: i64) -> i64 {
+    lhs / gcd(lhs, rhs) * rhs
+}
+
+// `(gcd, s, t)` with `lhs * s + rhs * t = gcd`
+fn extended_gcd(lhs: i64, rhs: i64) -> (i64, i64, i64) {
+    if rhs == 0 {
+        (lhs, 1, 0)
+    } else {
+        let (gcd, s, t) = extended_gcd(rhs, lhs % rhs);
+        (gcd, t, s - lhs / rhs * t)
+    }
+}
+
+// Solutions of `coefficient * t = rest mod modulus`, as `t = residue mod
+// step`
+fn solve_congruence(coefficient: i64, rest: i64, modulus: i64) -> Option<(i64, i64)> {
+    let (gcd, s, _) = extended_gcd(coefficient.rem_euclid(modulus), modulus);
+    if rest % gcd != 0 {
+        return None;
+    }
+    let step = modulus / gcd;
+    let residue = i128::from((rest / gcd).rem_euclid(step)) * i128::from(s.rem_euclid(step))
+        % i128::from(step);
+    Some((i64::try_from(residue).unwrap(), step))
+}
+
+// `t` within `0..lcm(lhs.1, rhs.1)` with `t = lhs.0 mod lhs.1` and
+// `t = rhs.0 mod rhs.1`
+fn chinese_remainder(lhs: (i64, i64), rhs: (i64, i64)) -> Option<i64> {
+    let (k, _) = solve_congruence(lhs.1, rhs.0 - lhs.0, rhs.1)?;
+    let modulus = lcm(lhs.1, rhs.1);
+    let time = i128::from(lhs.0) + i128::from(lhs.1) * i128::from(k);
+    Some(i64::try_from(time.rem_euclid(i128::from(modulus))).unwrap())
 }
 
 fn parse_input(reader: impl Read, bounds: (i64, i64)) -> impl Iterator<Item = Robot> {
@@ -82,12 +325,12 @@ fn parse_input(reader: impl Read, bounds: (i64, i64)) -> impl Iterator<Item = Ro
 
 fn count_robots_in_quadrant(
     quadrants: (isize, isize, isize, isize),
-    robot: impl Borrow<Robot>,
+    position: (i64, i64),
     bounds: (i64, i64),
 ) -> (isize, isize, isize, isize) {
     match (
-        robot.borrow().position.0.cmp(&(bounds.0 / 2)),
-        robot.borrow().position.1.cmp(&(bounds.1 / 2)),
+        position.0.cmp(&(bounds.0 / 2)),
+        position.1.cmp(&(bounds.1 / 2)),
     ) {
         (Ordering::Equal, _) | (_, Ordering::Equal) => quadrants,
         (Ordering::Less, Ordering::Less) => {
@@ -105,19 +348,28 @@ fn count_robots_in_quadrant(
     }
 }
 
-#[derive(Debug)]
-struct Robot {
+#[derive(Debug, Clone)]
+pub struct Robot {
     position: (i64, i64),
     velocity: (i64, i64),
     bounds: (i64, i64),
 }
 
 impl Robot {
-    fn step(&mut self, steps: i64) {
-        self.position.0 += self.velocity.0 * steps;
-        self.position.0 = self.position.0.rem_euclid(self.bounds.0);
-        self.position.1 += self.velocity.1 * steps;
-        self.position.1 = self.position.1.rem_euclid(self.bounds.1);
+    pub fn position(&self) -> (i64, i64) {
+        self.position
+    }
+
+    pub fn velocity(&self) -> (i64, i64) {
+        self.velocity
+    }
+
+    // Position after `time` seconds, `time` can be negative
+    pub fn position_at(&self, time: i64) -> (i64, i64) {
+        (
+            axis_at(self.position.0, self.velocity.0, self.bounds.0, time),
+            axis_at(self.position.1, self.velocity.1, self.bounds.1, time),
+        )
     }
 }
 
@@ -126,21 +378,21 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_step() {
-        let mut robot = Robot {
+    fn test_position_at() {
+        let robot = Robot {
             position: (2, 4),
             velocity: (2, -3),
             bounds: (11, 7),
         };
-        robot.step(1);
-        assert_eq!(robot.position, (4, 1));
-        robot.step(1);
-        assert_eq!(robot.position, (6, 5));
-        robot.step(1);
-        assert_eq!(robot.position, (8, 2));
-        robot.step(1);
-        assert_eq!(robot.position, (10, 6));
-        robot.step(1);
-        assert_eq!(robot.position, (1, 3));
+        assert_eq!(robot.position_at(1), (4, 1));
+        assert_eq!(robot.position_at(2), (6, 5));
+        assert_eq!(robot.position_at(3), (8, 2));
+        assert_eq!(robot.position_at(4), (10, 6));
+        assert_eq!(robot.position_at(5), (1, 3));
+        assert_eq!(robot.position_at(-1), robot.position_at(76));
+        assert_eq!(
+            robot.position_at(i64::MAX),
+            robot.position_at(i64::MAX % 77)
+        );
     }
 }
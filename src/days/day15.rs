@@ -1,57 +1,91 @@
-use std::io::{BufRead, BufReader, Read};
+use std::{
+    collections::{BTreeSet, VecDeque},
+    io::{BufRead, BufReader, Read},
+};
 
 use crate::{coord::Coord, direction::Direction};
 
 type RobotMove = Direction;
 
-fn run<T: Read>(reader: T, wide: bool) -> usize {
+fn run<T: Read>(reader: T, scale: Coord) -> usize {
+    let (mut warehouse, moves) = parse_puzzle(reader, scale);
+
+    for robot_move in moves {
+        warehouse.move_robot(robot_move);
+    }
+
+    warehouse.compute_gps()
+}
+
+// The warehouse, with boxes `scale.row` cells high and `scale.column` cells
+// wide, and the list of moves of the robot
+pub fn parse_puzzle(reader: impl Read, scale: Coord) -> (Warehouse, Vec<RobotMove>) {
     let mut buf = BufReader::with_capacity(10_000, reader);
-    let mut warehouse = Warehouse::parse(&mut buf, wide);
+    let warehouse = Warehouse::parse(&mut buf, scale);
 
     let mut moves = Vec::with_capacity(10_000);
     buf.read_to_end(&mut moves).unwrap();
 
-    for robot_move in moves.into_iter().filter(|c| c != &b'\n').map(|c| match c {
-        b'^' => RobotMove::North,
-        b'v' => RobotMove::South,
-        b'>' => RobotMove::East,
-        b'<' => RobotMove::West,
-        _ => unreachable!("Not a move"),
-    }) {
-        warehouse.move_robot(robot_move);
-    }
+    let moves = moves
+        .into_iter()
+        .filter(|c| !c.is_ascii_whitespace())
+        .map(|c| match c {
+            b'^' => RobotMove::North,
+            b'v' => RobotMove::South,
+            b'>' => RobotMove::East,
+            b'<' => RobotMove::West,
+            _ => unreachable!("Not a move"),
+        })
+        .collect();
 
-    warehouse.compute_gps()
+    (warehouse, moves)
 }
 
 pub fn part1<T: Read>(reader: T) -> usize {
-    run(reader, false)
+    run(reader, Coord::new(1, 1))
 }
 
 pub fn part2(reader: impl Read) -> usize {
-    run(reader, true)
+    run(reader, Coord::new(1, 2))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub robot_move: RobotMove,
+    // Position of the robot before the move
+    pub robot: Coord,
+    // False if the robot was blocked
+    pub moved: bool,
+    // Top left cell of every box pushed, before the move
+    pub boxes: Vec<Coord>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum WarehouseTile {
     Empty,
     Wall,
-    BoxLeft,
-    BoxRight,
+    // Offset of the cell from the top left corner of its box
+    Box { row: usize, column: usize },
     Robot,
 }
 
-#[derive(Debug)]
-struct Warehouse {
+#[derive(Debug, Clone)]
+pub struct Warehouse {
     robot: Coord,
     map: Vec<WarehouseTile>,
     dimensions: Coord,
-    wide: bool,
+    // Rows and columns of every box
+    scale: Coord,
+    history: Vec<Step>,
+    // Steps undone, the last one undone at the end
+    undone: Vec<Step>,
 }
 
 impl Warehouse {
-    fn parse<T: Read>(reader: &mut BufReader<T>, wide: bool) -> Self {
-        let multiplier = if wide { 2 } else { 1 };
+    // Every tile of the map becomes `scale.row` by `scale.column` cells, the
+    // robot stays on a single cell, the top left one
+    fn parse<T: Read>(reader: &mut BufReader<T>, scale: Coord) -> Self {
+        assert!(scale.row > 0 && scale.column > 0, "Scale can't be empty.");
         reader
             .lines()
             .enumerate()
@@ -61,40 +95,35 @@ impl Warehouse {
                     robot: Coord::default(),
                     map: Vec::with_capacity(10_000),
                     dimensions: Coord::default(),
-                    wide,
+                    scale,
+                    history: Vec::new(),
+                    undone: Vec::new(),
                 },
                 |mut warehouse, (row, line)| {
                     let line = line.unwrap();
-                    warehouse.dimensions = Coord::new(row + 1, line.len() * multiplier);
-
-                    for (column, c) in line.chars().enumerate() {
-                        match c {
-                            '#' => {
-                                warehouse.map.push(WarehouseTile::Wall);
-                                if wide {
-                                    warehouse.map.push(WarehouseTile::Wall);
-                                }
-                            }
-                            'O' => {
-                                warehouse.map.push(WarehouseTile::BoxLeft);
-                                if wide {
-                                    warehouse.map.push(WarehouseTile::BoxRight);
-                                }
-                            }
-                            '.' => {
-                                warehouse.map.push(WarehouseTile::Empty);
-                                if wide {
-                                    warehouse.map.push(WarehouseTile::Empty);
-                                }
-                            }
-                            '@' => {
-                                warehouse.map.push(WarehouseTile::Robot);
-                                if wide {
-                                    warehouse.map.push(WarehouseTile::Empty);
-                                }
-                                warehouse.robot = Coord::new(row, column * multiplier)
+                    warehouse.dimensions =
+                        Coord::new((row + 1) * scale.row, line.len() * scale.column);
+
+                    for box_row in 0..scale.row {
+                        for (column, c) in line.chars().enumerate() {
+                            for box_column in 0..scale.column {
+                                let tile = match c {
+                                    '#' => WarehouseTile::Wall,
+                                    'O' => WarehouseTile::Box {
+                                        row: box_row,
+                                        column: box_column,
+                                    },
+                                    '.' => WarehouseTile::Empty,
+                                    '@' if box_row == 0 && box_column == 0 => {
+                                        warehouse.robot =
+                                            Coord::new(row * scale.row, column * scale.column);
+                                        WarehouseTile::Robot
+                                    }
+                                    '@' => WarehouseTile::Empty,
+                                    _ => unreachable!("Not a tile"),
+                                };
+                                warehouse.map.push(tile);
                             }
-                            _ => unreachable!("Not a tile"),
                         }
                     }
 
@@ -103,7 +132,25 @@ impl Warehouse {
             )
     }
 
-    fn compute_gps(&self) -> usize {
+    pub fn robot(&self) -> Coord {
+        self.robot
+    }
+
+    pub fn dimensions(&self) -> Coord {
+        self.dimensions
+    }
+
+    // Top left cell of every box, in row major order
+    pub fn boxes(&self) -> Vec<Coord> {
+        self.map
+            .iter()
+            .enumerate()
+            .filter(|(_, tile)| **tile == WarehouseTile::Box { row: 0, column: 0 })
+            .map(|(i, _)| Coord::new(i / self.dimensions.column, i % self.dimensions.column))
+            .collect()
+    }
+
+    pub fn compute_gps(&self) -> usize {
         self.map
             .iter()
             .enumerate()
@@ -111,22 +158,91 @@ impl Warehouse {
                 let row = i / self.dimensions.column;
                 let column = i % self.dimensions.column;
                 match tile {
+                    WarehouseTile::Box { row: 0, column: 0 } => row * 100 + column,
                     WarehouseTile::Empty
                     | WarehouseTile::Wall
                     | WarehouseTile::Robot
-                    | WarehouseTile::BoxRight => 0,
-                    WarehouseTile::BoxLeft => row * 100 + column,
+                    | WarehouseTile::Box { .. } => 0,
                 }
             })
             .sum()
     }
 
+    // Steps applied so far, without the ones undone
+    pub fn history(&self) -> &[Step] {
+        &self.history
+    }
+
+    // Applies a move, forgetting any step undone
+    pub fn apply(&mut self, robot_move: RobotMove) -> &Step {
+        self.undone.clear();
+        self.move_robot(robot_move);
+        self.history.last().unwrap()
+    }
+
+    pub fn undo(&mut self) -> Option<&Step> {
+        let step = self.history.pop()?;
+        if step.moved {
+            let boxes = step
+                .boxes
+                .iter()
+                .map(|coord| step.robot_move.step(*coord))
+                .collect::<Vec<_>>();
+            self.shift(
+                step.robot_move.step(step.robot),
+                &boxes,
+                step.robot_move.reverse(),
+            );
+        }
+        self.undone.push(step);
+        self.undone.last()
+    }
+
+    pub fn redo(&mut self) -> Option<&Step> {
+        let step = self.undone.pop()?;
+        if step.moved {
+            self.shift(step.robot, &step.boxes, step.robot_move);
+        }
+        self.history.push(step);
+        self.history.last()
+    }
+
+    pub fn render(&self) -> String {
+        self.map
+            .chunks(self.dimensions.column)
+            .map(|row| {
+                row.iter()
+                    .map(|tile| match tile {
+                        WarehouseTile::Empty => '.',
+                        WarehouseTile::Wall => '#',
+                        WarehouseTile::Box { .. } if self.scale.column == 1 => 'O',
+                        WarehouseTile::Box { column: 0, .. } => '[',
+                        WarehouseTile::Box { column, .. } if *column == self.scale.column - 1 => {
+                            ']'
+                        }
+                        WarehouseTile::Box { .. } => '=',
+                        WarehouseTile::Robot => '@',
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
     fn move_robot(&mut self, robot_move: RobotMove) {
-        let next = robot_move.step(self.robot);
-        if self.can_push_box(next, robot_move, true) {
-            self.propagate_push(self.robot, robot_move, true);
-            self.robot = next;
+        let robot = self.robot;
+        let boxes = self.boxes_to_push(robot_move.step(robot), robot_move);
+        let moved = boxes.is_some();
+        let boxes = boxes.unwrap_or_default();
+        if moved {
+            self.shift(robot, &boxes, robot_move);
         }
+        self.history.push(Step {
+            robot_move,
+            robot,
+            moved,
+            boxes,
+        });
     }
 
     fn get_coord(&self, coord: Coord) -> WarehouseTile {
@@ -137,82 +253,240 @@ impl Warehouse {
         &mut self.map[coord.row * self.dimensions.column + coord.column]
     }
 
-    fn can_push_box(&mut self, coord: Coord, robot_move: RobotMove, check_sides: bool) -> bool {
-        match self.get_coord(coord) {
-            WarehouseTile::Empty => true,
-            WarehouseTile::Wall => false,
-            WarehouseTile::BoxLeft => {
-                let next = robot_move.step(coord);
-                let right = coord + (0, 1);
-
-                if right == next {
-                    self.can_push_box(next, robot_move, false)
-                } else {
-                    self.can_push_box(next, robot_move, true)
-                        && if self.wide && check_sides {
-                            self.can_push_box(right, robot_move, false)
-                        } else {
-                            true
-                        }
+    fn box_cells(&self, top_left: Coord) -> impl Iterator<Item = (Coord, WarehouseTile)> {
+        let columns = self.scale.column;
+        (0..self.scale.row).flat_map(move |row| {
+            (0..columns)
+                .map(move |column| (top_left + (row, column), WarehouseTile::Box { row, column }))
+        })
+    }
+
+    // Boxes pushed when the robot moves into `coord`, `None` if one of them
+    // would hit a wall
+    fn boxes_to_push(&self, coord: Coord, robot_move: RobotMove) -> Option<Vec<Coord>> {
+        let mut boxes = Vec::new();
+        let mut pushed = BTreeSet::new();
+        let mut queue = VecDeque::from([coord]);
+
+        while let Some(coord) = queue.pop_front() {
+            let top_left = match self.get_coord(coord) {
+                WarehouseTile::Empty => continue,
+                WarehouseTile::Wall => return None,
+                WarehouseTile::Box { row, column } => coord - (row, column),
+                WarehouseTile::Robot => unreachable!("Robot can't push itself"),
+            };
+            if !pushed.insert(top_left) {
+                continue;
+            }
+            boxes.push(top_left);
+            let cells = self
+                .box_cells(top_left)
+                .map(|(cell, _)| cell)
+                .collect::<Vec<_>>();
+            queue.extend(
+                cells
+                    .iter()
+                    .map(|cell| robot_move.step(*cell))
+                    .filter(|next| !cells.contains(next)),
+            );
+        }
+
+        Some(boxes)
+    }
+
+    // Moves the robot and the boxes one cell, their destinations must be free
+    // or part of what is moved. Undoing pulls the boxes into the cell of the
+    // robot, so it is cleared first.
+    fn shift(&mut self, robot: Coord, boxes: &[Coord], robot_move: RobotMove) {
+        let cells = boxes
+            .iter()
+            .flat_map(|top_left| self.box_cells(*top_left))
+            .collect::<Vec<_>>();
+        *self.get_coord_mut(robot) = WarehouseTile::Empty;
+        for (cell, _) in &cells {
+            *self.get_coord_mut(*cell) = WarehouseTile::Empty;
+        }
+        for (cell, tile) in cells {
+            *self.get_coord_mut(robot_move.step(cell)) = tile;
+        }
+        self.robot = robot_move.step(robot);
+        *self.get_coord_mut(self.robot) = WarehouseTile::Robot;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MoveSearch {
+    Found(Vec<RobotMove>),
+    // Every reachable arrangement was explored
+    Impossible,
+    NodeLimit,
+}
+
+// Robot and top left cell of the boxes, sorted
+type SearchState = (Coord, Vec<Coord>);
+
+impl Warehouse {
+    // Shortest list of moves putting the boxes on `target`, the top left cells
+    // of the boxes wanted, wherever the robot ends. This is a breadth first
+    // search over the robot and box positions, pruning arrangements with a box
+    // on a cell from which it can't be pushed to any target, even ignoring
+    // the other boxes. At most `node_limit` arrangements are expanded.
+    pub fn find_moves(&self, target: &[Coord], node_limit: usize) -> MoveSearch {
+        let mut target = target.to_vec();
+        target.sort();
+        target.dedup();
+
+        let start = (self.robot, self.boxes());
+        if start.1.len() != target.len() || !target.iter().all(|coord| self.fits_box(*coord)) {
+            return MoveSearch::Impossible;
+        }
+
+        let live = self.live_box_positions(&target);
+        if !start.1.iter().all(|coord| live.contains(coord)) {
+            return MoveSearch::Impossible;
+        }
+
+        let mut empty = self.clone();
+        empty.history.clear();
+        empty.undone.clear();
+        for tile in empty.map.iter_mut() {
+            if *tile != WarehouseTile::Wall {
+                *tile = WarehouseTile::Empty;
+            }
+        }
+
+        // State, index of the previous node and move leading to it
+        let mut nodes: Vec<(SearchState, usize, Option<RobotMove>)> =
+            vec![(start.clone(), 0, None)];
+        let mut seen = BTreeSet::from([start]);
+        let mut next = 0;
+
+        while next < nodes.len() {
+            if next >= node_limit {
+                return MoveSearch::NodeLimit;
+            }
+            let ((robot, boxes), _, _) = &nodes[next];
+            if *boxes == target {
+                let mut moves = Vec::new();
+                let mut node = next;
+                while let (_, previous, Some(robot_move)) = &nodes[node] {
+                    moves.push(*robot_move);
+                    node = *previous;
+                }
+                moves.reverse();
+                return MoveSearch::Found(moves);
+            }
+
+            let mut warehouse = empty.clone();
+            for coord in boxes {
+                for (cell, tile) in warehouse.box_cells(*coord).collect::<Vec<_>>() {
+                    *warehouse.get_coord_mut(cell) = tile;
                 }
             }
-            WarehouseTile::BoxRight => {
-                let next = robot_move.step(coord);
-                let left = coord - (0, 1);
-
-                if left == next {
-                    self.can_push_box(next, robot_move, false)
-                } else {
-                    self.can_push_box(next, robot_move, true)
-                        && if check_sides {
-                            self.can_push_box(left, robot_move, false)
+            *warehouse.get_coord_mut(*robot) = WarehouseTile::Robot;
+
+            let robot = *robot;
+            let boxes = boxes.clone();
+            for robot_move in [
+                RobotMove::North,
+                RobotMove::South,
+                RobotMove::East,
+                RobotMove::West,
+            ] {
+                let Some(pushed) = warehouse.boxes_to_push(robot_move.step(robot), robot_move)
+                else {
+                    continue;
+                };
+                if !pushed
+                    .iter()
+                    .all(|coord| live.contains(&robot_move.step(*coord)))
+                {
+                    continue;
+                }
+                let mut moved = boxes
+                    .iter()
+                    .map(|coord| {
+                        if pushed.contains(coord) {
+                            robot_move.step(*coord)
                         } else {
-                            true
+                            *coord
                         }
+                    })
+                    .collect::<Vec<_>>();
+                moved.sort();
+                let state = (robot_move.step(robot), moved);
+                if seen.insert(state.clone()) {
+                    nodes.push((state, next, Some(robot_move)));
                 }
             }
-            _ => unreachable!("Invalid coord for move"),
+
+            next += 1;
         }
+
+        MoveSearch::Impossible
     }
 
-    fn propagate_push(&mut self, coord: Coord, robot_move: RobotMove, propagate_sides: bool) {
-        match self.get_coord(coord) {
-            WarehouseTile::Empty => (),
-            WarehouseTile::Wall => unreachable!("Wall should not be part of propagation"),
-            WarehouseTile::BoxLeft => {
-                let next = robot_move.step(coord);
-                let right = coord + (0, 1);
-                if right == next {
-                    self.propagate_push(next, robot_move, false);
-                } else {
-                    self.propagate_push(next, robot_move, true);
-                    if self.wide && propagate_sides {
-                        self.propagate_push(right, robot_move, false);
-                    }
+    // A box with its top left cell on `coord` would only cover empty cells
+    fn fits_box(&self, coord: Coord) -> bool {
+        coord.row + self.scale.row <= self.dimensions.row
+            && coord.column + self.scale.column <= self.dimensions.column
+            && self
+                .box_cells(coord)
+                .all(|(cell, _)| self.get_coord(cell) != WarehouseTile::Wall)
+    }
+
+    // Positions from which a lone box can be pushed onto one of the targets,
+    // found by pulling the boxes back from the targets
+    fn live_box_positions(&self, target: &[Coord]) -> BTreeSet<Coord> {
+        let mut live = target.iter().copied().collect::<BTreeSet<_>>();
+        let mut queue = target.iter().copied().collect::<VecDeque<_>>();
+
+        while let Some(coord) = queue.pop_front() {
+            for robot_move in [
+                RobotMove::North,
+                RobotMove::South,
+                RobotMove::East,
+                RobotMove::West,
+            ] {
+                // The box came from `previous`, pushed by the robot standing
+                // behind one of its cells
+                let Some(previous) = self.step_within(coord, robot_move.reverse()) else {
+                    continue;
+                };
+                if live.contains(&previous) || !self.fits_box(previous) {
+                    continue;
                 }
-                *self.get_coord_mut(next) = WarehouseTile::BoxLeft;
-                *self.get_coord_mut(coord) = WarehouseTile::Empty;
-            }
-            WarehouseTile::BoxRight => {
-                let next = robot_move.step(coord);
-                let left = coord - (0, 1);
-                if left == next {
-                    self.propagate_push(next, robot_move, false);
-                } else {
-                    self.propagate_push(next, robot_move, true);
-                    if self.wide && propagate_sides {
-                        self.propagate_push(left, robot_move, false);
-                    }
+                let cells = self
+                    .box_cells(previous)
+                    .map(|(cell, _)| cell)
+                    .collect::<Vec<_>>();
+                let pushable = cells.iter().any(|cell| {
+                    self.step_within(*cell, robot_move.reverse())
+                        .filter(|behind| !cells.contains(behind))
+                        .is_some_and(|behind| self.get_coord(behind) != WarehouseTile::Wall)
+                });
+                if pushable {
+                    live.insert(previous);
+                    queue.push_back(previous);
                 }
-                *self.get_coord_mut(next) = WarehouseTile::BoxRight;
-                *self.get_coord_mut(coord) = WarehouseTile::Empty;
-            }
-            WarehouseTile::Robot => {
-                let next = robot_move.step(coord);
-                self.propagate_push(next, robot_move, true);
-                *self.get_coord_mut(next) = WarehouseTile::Robot;
-                *self.get_coord_mut(coord) = WarehouseTile::Empty;
             }
-        };
+        }
+
+        live
+    }
+
+    fn step_within(&self, coord: Coord, robot_move: RobotMove) -> Option<Coord> {
+        match robot_move {
+            RobotMove::North => coord
+                .row
+                .checked_sub(1)
+                .map(|row| Coord::new(row, coord.column)),
+            RobotMove::West => coord
+                .column
+                .checked_sub(1)
+                .map(|column| Coord::new(coord.row, column)),
+            RobotMove::South | RobotMove::East => Some(robot_move.step(coord)),
+        }
+        .filter(|next| next.row < self.dimensions.row && next.column < self.dimensions.column)
     }
 }
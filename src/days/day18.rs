@@ -3,7 +3,7 @@ use std::{
     io::{BufRead, BufReader, Read},
 };
 
-use crate::{coord::Coord, vec2d::Vec2d};
+use crate::{coord::Coord, disjoint_set::DisjointSet, vec2d::Vec2d};
 
 fn parse_input(reader: impl Read) -> impl Iterator<Item = Coord> {
     BufReader::with_capacity(10_000, reader)
@@ -43,27 +43,221 @@ pub fn part2_for_test(reader: impl Read) -> Coord {
 
 fn part2_internal(reader: impl Read, target: Coord) -> Coord {
     let obstacles = parse_input(reader).collect::<Vec<_>>();
+    let bounds = Coord::new(target.row + 1, target.column + 1);
 
-    let mut left = 0;
-    let mut right = obstacles.len();
+    let blockage = obstacles[cutting_byte(&obstacles, bounds).expect("Path should get cut.")];
 
-    let blockage = loop {
-        if left == right {
-            break obstacles[left];
+    Coord::new(blockage.column, blockage.row)
+}
+
+type FreeCells = DisjointSet<(usize, usize), ()>;
+
+// Index of the byte after which the end can't be reached anymore. All the
+// bytes are dropped first, then removed from the last one, joining the free
+// cells, until the start and the end are in the same set.
+fn cutting_byte(obstacles: &[Coord], bounds: Coord) -> Option<usize> {
+    let start = (0, 0);
+    let end = (bounds.row - 1, bounds.column - 1);
+
+    // Index of the first byte falling on each cell
+    let mut fallen = vec![None; bounds.row * bounds.column];
+    for (i, obstacle) in obstacles.iter().enumerate().rev() {
+        fallen[obstacle.row * bounds.column + obstacle.column] = Some(i);
+    }
+
+    let mut cells = FreeCells::new(|_, _| ());
+    let free = |coord: Coord, cells: &mut FreeCells| {
+        cells.insert((coord.row, coord.column), ());
+        for adjacent in coord.adjacent_4_way(bounds).into_iter().flatten() {
+            cells.union(&(coord.row, coord.column), &(adjacent.row, adjacent.column));
+        }
+    };
+
+    for row in 0..bounds.row {
+        for column in 0..bounds.column {
+            if fallen[row * bounds.column + column].is_none() {
+                free(Coord::new(row, column), &mut cells);
+            }
+        }
+    }
+    if cells.same_set(&start, &end) {
+        return None;
+    }
+
+    for (i, obstacle) in obstacles.iter().enumerate().rev() {
+        if fallen[obstacle.row * bounds.column + obstacle.column] != Some(i) {
+            continue;
+        }
+        free(*obstacle, &mut cells);
+        if cells.same_set(&start, &end) {
+            return Some(i);
         }
-        let mid = left + (right - left) / 2;
-        let maze = Maze {
-            obstacles: obstacles.iter().copied().take(mid + 1).collect(),
-            bounds: Coord::new(target.row + 1, target.column + 1),
+    }
+
+    None
+}
+
+// Bytes falling one at a time on a memory space of `bounds`, keeping the
+// shortest path from the top left to the bottom right corner. The path is
+// only searched again when a byte falls on it.
+#[derive(Debug, Clone)]
+pub struct FallingBytes {
+    bounds: Coord,
+    obstacles: Vec<bool>,
+    path: Vec<bool>,
+    steps: Option<usize>,
+    fallen: usize,
+    cut_by: Option<usize>,
+}
+
+impl FallingBytes {
+    pub fn new(bounds: Coord) -> Self {
+        let mut falling_bytes = Self {
+            bounds,
+            obstacles: vec![false; bounds.row * bounds.column],
+            path: vec![false; bounds.row * bounds.column],
+            steps: None,
+            fallen: 0,
+            cut_by: None,
         };
-        if maze.count_steps().is_some() {
-            left = mid + 1;
-        } else {
-            right = mid;
+        falling_bytes.find_path();
+        falling_bytes
+    }
+
+    // Length of the shortest path, `None` once it is cut
+    pub fn steps(&self) -> Option<usize> {
+        self.steps
+    }
+
+    // Index of the byte that cut the path
+    pub fn cut_by(&self) -> Option<usize> {
+        self.cut_by
+    }
+
+    // Drops a byte and returns the length of the shortest path after it
+    pub fn push(&mut self, byte: Coord) -> Option<usize> {
+        let index = byte.row * self.bounds.column + byte.column;
+        self.obstacles[index] = true;
+        self.fallen += 1;
+
+        if self.path[index] {
+            self.find_path();
+            if self.steps.is_none() {
+                self.cut_by = Some(self.fallen - 1);
+            }
         }
-    };
 
-    Coord::new(blockage.column, blockage.row)
+        self.steps
+    }
+
+    fn find_path(&mut self) {
+        let start = Coord::new(0, 0);
+        let end = self.bounds - (1, 1);
+        self.path.fill(false);
+        self.steps = None;
+        if self.obstacles[0] {
+            return;
+        }
+
+        let mut previous = vec![None; self.obstacles.len()];
+        let mut queue = VecDeque::from([start]);
+        previous[0] = Some(start);
+
+        while let Some(coord) = queue.pop_front() {
+            if coord == end {
+                let mut steps = 0;
+                let mut coord = coord;
+                self.path[coord.row * self.bounds.column + coord.column] = true;
+                while coord != start {
+                    coord = previous[coord.row * self.bounds.column + coord.column].unwrap();
+                    self.path[coord.row * self.bounds.column + coord.column] = true;
+                    steps += 1;
+                }
+                self.steps = Some(steps);
+                return;
+            }
+            for next in coord.adjacent_4_way(self.bounds).into_iter().flatten() {
+                let index = next.row * self.bounds.column + next.column;
+                if !self.obstacles[index] && previous[index].is_none() {
+                    previous[index] = Some(coord);
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+}
+
+// Every byte of the input, as row and column, with the length of the shortest
+// path once it has fallen
+pub fn stream(reader: impl Read, target: Coord) -> impl Iterator<Item = (Coord, Option<usize>)> {
+    let mut falling_bytes = FallingBytes::new(Coord::new(target.row + 1, target.column + 1));
+    parse_input(reader).map(move |byte| (byte, falling_bytes.push(byte)))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimedRoute {
+    // Steps taken to reach the end
+    pub arrival: usize,
+    // Cells from the start to the end, both included
+    pub path: Vec<Coord>,
+}
+
+// Route from the top left to the bottom right corner while bytes keep
+// falling, `fallen` of them at first and then one after each step. A cell
+// can't be entered at the time its byte falls.
+pub fn route_while_falling(reader: impl Read, target: Coord, fallen: usize) -> Option<TimedRoute> {
+    let obstacles = parse_input(reader).collect::<Vec<_>>();
+    timed_route(
+        &obstacles,
+        Coord::new(target.row + 1, target.column + 1),
+        fallen,
+    )
+}
+
+// Search over `(Coord, time)`. Cells only ever get blocked, so arriving
+// later or waiting never helps: the earliest arrival on each cell is the only
+// state worth keeping, and breadth first order gives it.
+fn timed_route(obstacles: &[Coord], bounds: Coord, fallen: usize) -> Option<TimedRoute> {
+    // Time from which each cell is blocked
+    let mut blocked_at = vec![usize::MAX; bounds.row * bounds.column];
+    for (i, obstacle) in obstacles.iter().enumerate().rev() {
+        blocked_at[obstacle.row * bounds.column + obstacle.column] = (i + 1).saturating_sub(fallen);
+    }
+
+    let start = Coord::new(0, 0);
+    let end = bounds - (1, 1);
+    if blocked_at[0] == 0 {
+        return None;
+    }
+
+    let mut previous = vec![None; bounds.row * bounds.column];
+    previous[0] = Some(start);
+    let mut queue = VecDeque::from([(start, 0)]);
+
+    while let Some((coord, time)) = queue.pop_front() {
+        if coord == end {
+            let mut path = vec![coord];
+            let mut coord = coord;
+            while coord != start {
+                coord = previous[coord.row * bounds.column + coord.column].unwrap();
+                path.push(coord);
+            }
+            path.reverse();
+            return Some(TimedRoute {
+                arrival: time,
+                path,
+            });
+        }
+        for next in coord.adjacent_4_way(bounds).into_iter().flatten() {
+            let index = next.row * bounds.column + next.column;
+            if previous[index].is_none() && blocked_at[index] > time + 1 {
+                previous[index] = Some(coord);
+                queue.push_back((next, time + 1));
+            }
+        }
+    }
+
+    None
 }
 
 struct Maze {
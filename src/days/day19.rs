@@ -1,4 +1,4 @@
-use std::{collections::HashMap, fmt::Debug, io::Read, sync::Mutex};
+use std::{cmp::Reverse, collections::BTreeMap, io::Read};
 
 pub fn part1(mut reader: impl Read) -> usize {
     let mut data = Vec::with_capacity(100_000);
@@ -6,7 +6,10 @@ pub fn part1(mut reader: impl Read) -> usize {
 
     let (towels, patterns) = parse_input(data.as_slice());
 
-    match_patterns(&towels, patterns.as_slice())
+    patterns
+        .into_iter()
+        .filter(|pattern| towels.is_possible(pattern))
+        .count()
 }
 
 pub fn part2(mut reader: impl Read) -> usize {
@@ -15,192 +18,524 @@ pub fn part2(mut reader: impl Read) -> usize {
 
     let (towels, patterns) = parse_input(data.as_slice());
 
-    count_patterns(&towels, patterns.as_slice())
+    patterns
+        .into_iter()
+        .map(|pattern| towels.count_arrangements(pattern))
+        .sum()
 }
 
-fn parse_input(data: &[u8]) -> (Towels, Vec<&[u8]>) {
+pub fn parse_input(data: &[u8]) -> (Towels, Vec<&[u8]>) {
     let mut lines = data.split(|c| *c == b'\n');
 
-    let towels = lines
-        .next()
-        .unwrap()
-        .split(|c| *c == b',')
-        .map(|slice| if slice[0] == b' ' { &slice[1..] } else { slice })
-        .fold(Towels::default(), |mut towels, towel| {
-            match towel[0] {
-                b'b' => towels.black.push(towel),
-                b'w' => towels.white.push(towel),
-                b'r' => towels.red.push(towel),
-                b'g' => towels.green.push(towel),
-                b'u' => towels.blue.push(towel),
-                _ => unreachable!("invalid towel stripe"),
+    let towels = Towels::new(
+        lines
+            .next()
+            .unwrap()
+            .split(|c| *c == b',')
+            .map(|towel| towel.trim_ascii()),
+    );
+
+    let patterns = lines
+        .map(|line| line.trim_ascii())
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    (towels, patterns)
+}
+
+#[derive(Debug, Default, Clone)]
+struct TrieNode {
+    children: BTreeMap<u8, usize>,
+    // Index of the towel ending on this node
+    towel: Option<usize>,
+}
+
+// Trie of the towels, any byte can be a stripe colour
+#[derive(Debug, Clone)]
+pub struct Towels {
+    towels: Vec<Vec<u8>>,
+    nodes: Vec<TrieNode>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArrangementOrder {
+    // Compares the towels one by one. As all the towels that fit at some
+    // position are prefixes of each other, the shortest comes first.
+    Lexicographic,
+    // Lexicographic among the arrangements with the same number of towels
+    FewestTowels,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternMatch<'a> {
+    pub arrangements: usize,
+    pub fewest: Option<Vec<&'a [u8]>>,
+    // Arrangement picking the shortest towel that works at each position
+    pub example: Option<Vec<&'a [u8]>>,
+}
+
+impl Towels {
+    // Empty and duplicated towels are ignored
+    pub fn new<T: AsRef<[u8]>>(towels: impl IntoIterator<Item = T>) -> Self {
+        let mut trie = Self {
+            towels: Vec::new(),
+            nodes: vec![TrieNode::default()],
+        };
+        for towel in towels {
+            trie.insert(towel.as_ref());
+        }
+        trie
+    }
+
+    fn insert(&mut self, towel: &[u8]) {
+        if towel.is_empty() {
+            return;
+        }
+        let mut node = 0;
+        for stripe in towel {
+            node = match self.nodes[node].children.get(stripe) {
+                Some(child) => *child,
+                None => {
+                    self.nodes.push(TrieNode::default());
+                    let child = self.nodes.len() - 1;
+                    self.nodes[node].children.insert(*stripe, child);
+                    child
+                }
+            };
+        }
+        if self.nodes[node].towel.is_none() {
+            self.nodes[node].towel = Some(self.towels.len());
+            self.towels.push(towel.to_vec());
+        }
+    }
+
+    pub fn towels(&self) -> &[Vec<u8>] {
+        &self.towels
+    }
+
+    // Towels that are a prefix of `pattern`, shortest first
+    fn prefixes<'a>(&'a self, pattern: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+        pattern
+            .iter()
+            .scan(0, |node, stripe| {
+                *node = *self.nodes[*node].children.get(stripe)?;
+                Some(*node)
+            })
+            .filter_map(|node| self.nodes[node].towel)
+    }
+
+    // Number of arrangements of `pattern[i..]` for every `i`, including the
+    // empty end of the pattern
+    fn suffix_arrangements(&self, pattern: &[u8]) -> Vec<usize> {
+        let mut arrangements = vec![0; pattern.len() + 1];
+        arrangements[pattern.len()] = 1;
+        for start in (0..pattern.len()).rev() {
+            arrangements[start] = self
+                .prefixes(&pattern[start..])
+                .map(|towel| arrangements[start + self.towels[towel].len()])
+                .sum();
+        }
+        arrangements
+    }
+
+    pub fn count_arrangements(&self, pattern: &[u8]) -> usize {
+        self.suffix_arrangements(pattern)[0]
+    }
+
+    pub fn is_possible(&self, pattern: &[u8]) -> bool {
+        self.is_possible_with(pattern, |_| true)
+    }
+
+    // Same as `is_possible`, only using the towels for which `allowed` holds
+    fn is_possible_with(&self, pattern: &[u8], allowed: impl Fn(usize) -> bool) -> bool {
+        let mut possible = vec![false; pattern.len() + 1];
+        possible[pattern.len()] = true;
+        for start in (0..pattern.len()).rev() {
+            possible[start] = self
+                .prefixes(&pattern[start..])
+                .any(|towel| allowed(towel) && possible[start + self.towels[towel].len()]);
+        }
+        possible[0]
+    }
+
+    pub fn match_pattern(&self, pattern: &[u8]) -> PatternMatch<'_> {
+        let arrangements = self.suffix_arrangements(pattern);
+
+        // Fewest towels for `pattern[i..]`, with the first towel to use
+        let mut fewest: Vec<Option<(usize, usize)>> = vec![None; pattern.len() + 1];
+        fewest[pattern.len()] = Some((0, usize::MAX));
+        for start in (0..pattern.len()).rev() {
+            fewest[start] = self
+                .prefixes(&pattern[start..])
+                .filter_map(|towel| {
+                    fewest[start + self.towels[towel].len()].map(|(count, _)| (count + 1, towel))
+                })
+                .min_by_key(|(count, _)| *count);
+        }
+
+        let fewest = fewest[0].map(|_| {
+            let mut start = 0;
+            let mut arrangement = Vec::new();
+            while start < pattern.len() {
+                let (_, towel) = fewest[start].unwrap();
+                arrangement.push(self.towels[towel].as_slice());
+                start += self.towels[towel].len();
             }
-            towels
+            arrangement
         });
 
-    lines.next().unwrap();
+        let example = (arrangements[0] > 0).then(|| {
+            let mut start = 0;
+            let mut arrangement = Vec::new();
+            while start < pattern.len() {
+                let towel = self
+                    .prefixes(&pattern[start..])
+                    .find(|towel| arrangements[start + self.towels[*towel].len()] > 0)
+                    .unwrap();
+                arrangement.push(self.towels[towel].as_slice());
+                start += self.towels[towel].len();
+            }
+            arrangement
+        });
 
-    let patterns = lines.collect();
+        PatternMatch {
+            arrangements: arrangements[0],
+            fewest,
+            example,
+        }
+    }
 
-    (towels, patterns)
-}
+    // Number of arrangements of `pattern[..i]` for every `i`
+    fn prefix_arrangements(&self, pattern: &[u8]) -> Vec<usize> {
+        let mut arrangements = vec![0; pattern.len() + 1];
+        arrangements[0] = 1;
+        for start in 0..pattern.len() {
+            if arrangements[start] == 0 {
+                continue;
+            }
+            for towel in self.prefixes(&pattern[start..]) {
+                arrangements[start + self.towels[towel].len()] += arrangements[start];
+            }
+        }
+        arrangements
+    }
 
-fn match_patterns(towels: &Towels, patterns: &[&[u8]]) -> usize {
-    let iter = Mutex::new(patterns.iter().filter(|pattern| !pattern.is_empty()));
+    // Arrangements of `pattern` in the given order. Skipping arrangements,
+    // with `nth` or `skip`, doesn't go through them, so pages can be taken
+    // anywhere.
+    pub fn arrangements<'a>(
+        &'a self,
+        pattern: &'a [u8],
+        order: ArrangementOrder,
+    ) -> Arrangements<'a> {
+        let counts = match order {
+            ArrangementOrder::Lexicographic => self
+                .suffix_arrangements(pattern)
+                .into_iter()
+                .map(|count| vec![count])
+                .collect(),
+            ArrangementOrder::FewestTowels => {
+                // Arrangements of `pattern[i..]` with exactly `k` towels
+                let mut counts = vec![vec![]; pattern.len() + 1];
+                counts[pattern.len()] = vec![1];
+                for start in (0..pattern.len()).rev() {
+                    let mut by_towels = vec![0; pattern.len() - start + 1];
+                    for towel in self.prefixes(&pattern[start..]) {
+                        let next = &counts[start + self.towels[towel].len()];
+                        for (towels, count) in next.iter().enumerate() {
+                            by_towels[towels + 1] += count;
+                        }
+                    }
+                    counts[start] = by_towels;
+                }
+                counts
+            }
+        };
+        let total = counts[0].iter().sum();
 
-    std::thread::scope(|scope| {
-        let (sender, receiver) = std::sync::mpsc::channel();
+        Arrangements {
+            towels: self,
+            pattern,
+            order,
+            counts,
+            rank: 0,
+            total,
+        }
+    }
 
-        for _ in 0..std::thread::available_parallelism().unwrap().get() {
-            let sender_clone = sender.clone();
-            let iter_ref = &iter;
+    // Number of times each towel, in the order of `towels`, is used across all
+    // the arrangements of all the patterns
+    pub fn usage(&self, patterns: &[&[u8]]) -> Vec<usize> {
+        let mut usage = vec![0; self.towels.len()];
+        for pattern in patterns {
+            let before = self.prefix_arrangements(pattern);
+            let after = self.suffix_arrangements(pattern);
+            for start in 0..pattern.len() {
+                if before[start] == 0 {
+                    continue;
+                }
+                for towel in self.prefixes(&pattern[start..]) {
+                    usage[towel] += before[start] * after[start + self.towels[towel].len()];
+                }
+            }
+        }
+        usage
+    }
 
-            scope.spawn(move || {
-                let sender = sender_clone;
-                let iter = iter_ref;
-                let mut cache = PatternCache::default();
+    // Towels that can also be arranged from other towels
+    pub fn redundant(&self) -> Vec<&[u8]> {
+        self.towels
+            .iter()
+            .filter(|towel| self.count_arrangements(towel) > 1)
+            .map(|towel| towel.as_slice())
+            .collect()
+    }
 
-                let mut pattern_opt = iter.lock().unwrap().next();
-                loop {
-                    let Some(pattern) = pattern_opt else {
-                        break;
-                    };
-                    let possible = pattern_possible(pattern, towels, &mut cache);
-                    if possible {
-                        sender.send(()).unwrap();
+    // Smallest subset of the towels keeping possible every pattern that is
+    // possible with all of them. Towels that some pattern can't do without
+    // are kept, and every other towel used by a pattern is searched, trying
+    // to drop it before keeping it, starting from a subset that is minimal
+    // for inclusion. The search stops after `node_limit` nodes, `minimum`
+    // tells whether it went through.
+    pub fn minimal_set(&self, patterns: &[&[u8]], node_limit: usize) -> MinimalSet<'_> {
+        let patterns = patterns
+            .iter()
+            .copied()
+            .filter(|pattern| self.is_possible(pattern))
+            .collect::<Vec<_>>();
+
+        // Patterns with an arrangement using each towel, the only ones that
+        // can become impossible without it
+        let mut users = vec![Vec::new(); self.towels.len()];
+        for (index, pattern) in patterns.iter().enumerate() {
+            let before = self.prefix_arrangements(pattern);
+            let after = self.suffix_arrangements(pattern);
+            for start in (0..pattern.len()).filter(|start| before[*start] > 0) {
+                for towel in self.prefixes(&pattern[start..]) {
+                    let used = after[start + self.towels[towel].len()] > 0;
+                    if used && users[towel].last() != Some(&index) {
+                        users[towel].push(index);
                     }
-                    pattern_opt = iter.lock().unwrap().next();
                 }
-            });
+            }
         }
 
-        std::mem::drop(sender);
+        let enabled = users
+            .iter()
+            .map(|users| !users.is_empty())
+            .collect::<Vec<_>>();
+        let mandatory = (0..self.towels.len())
+            .map(|towel| {
+                users[towel].iter().any(|pattern| {
+                    !self.is_possible_with(patterns[*pattern], |other| {
+                        other != towel && enabled[other]
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut candidates = (0..self.towels.len())
+            .filter(|towel| enabled[*towel] && !mandatory[*towel])
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|towel| Reverse(self.towels[*towel].len()));
 
-        receiver.into_iter().count()
-    })
+        let mut search = SetSearch {
+            towels: self,
+            patterns,
+            users,
+            enabled,
+            candidates,
+            best: Vec::new(),
+            nodes: 0,
+            node_limit,
+        };
+        search.best = search.drop_greedily();
+        let kept = (0..self.towels.len())
+            .filter(|towel| mandatory[*towel])
+            .collect();
+        search.search(0, kept);
+
+        let mut kept = vec![false; self.towels.len()];
+        for towel in &search.best {
+            kept[*towel] = true;
+        }
+        let status = (0..self.towels.len())
+            .map(|towel| {
+                if kept[towel] {
+                    TowelStatus::Kept
+                } else if self.is_possible_with(&self.towels[towel], |towel| kept[towel]) {
+                    TowelStatus::Redundant
+                } else {
+                    TowelStatus::Unused
+                }
+            })
+            .collect();
+
+        MinimalSet {
+            towels: (0..self.towels.len())
+                .filter(|towel| kept[*towel])
+                .map(|towel| self.towels[towel].as_slice())
+                .collect(),
+            status,
+            minimum: search.nodes <= search.node_limit,
+        }
+    }
 }
 
-fn pattern_possible<'a>(
-    pattern: &'a [u8],
-    towels: &Towels<'a>,
-    cache: &mut PatternCache<'a, bool>,
-) -> bool {
-    if pattern.is_empty() {
-        return true;
-    }
-    let towels_with_stripe = match pattern[0] {
-        b'b' => &towels.black,
-        b'w' => &towels.white,
-        b'r' => &towels.red,
-        b'g' => &towels.green,
-        b'u' => &towels.blue,
-        _ => unreachable!("invalid towel stripe"),
-    };
-    towels_with_stripe
-        .iter()
-        .filter(|towel| pattern.starts_with(towel))
-        .any(|towel| {
-            if let Some(cached) = cache.get(&pattern[towel.len()..]) {
-                cached
-            } else if pattern_possible(&pattern[towel.len()..], towels, cache) {
-                cache.insert(&pattern[towel.len()..], true);
-                true
-            } else {
-                cache.insert(&pattern[towel.len()..], false);
-                false
-            }
-        })
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TowelStatus {
+    Kept,
+    // Not kept, but can be arranged from the kept towels
+    Redundant,
+    // Not kept, and not needed by any of the patterns
+    Unused,
 }
 
-fn count_patterns(towels: &Towels, patterns: &[&[u8]]) -> usize {
-    let iter = Mutex::new(patterns.iter().filter(|pattern| !pattern.is_empty()));
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinimalSet<'a> {
+    // Kept towels, in the order of `Towels::towels`
+    pub towels: Vec<&'a [u8]>,
+    // Status of every towel, in the order of `Towels::towels`
+    pub status: Vec<TowelStatus>,
+    // No smaller subset exists, otherwise the subset is only minimal for
+    // inclusion
+    pub minimum: bool,
+}
 
-    std::thread::scope(|scope| {
-        let (sender, receiver) = std::sync::mpsc::channel();
+struct SetSearch<'a> {
+    towels: &'a Towels,
+    patterns: Vec<&'a [u8]>,
+    users: Vec<Vec<usize>>,
+    // Towels kept or not decided yet
+    enabled: Vec<bool>,
+    // Towels to decide, longest first
+    candidates: Vec<usize>,
+    best: Vec<usize>,
+    nodes: usize,
+    node_limit: usize,
+}
 
-        for _ in 0..std::thread::available_parallelism().unwrap().get() {
-            let sender_clone = sender.clone();
-            let iter_ref = &iter;
+impl SetSearch<'_> {
+    fn can_drop(&self, towel: usize) -> bool {
+        self.users[towel].iter().all(|pattern| {
+            self.towels
+                .is_possible_with(self.patterns[*pattern], |other| {
+                    other != towel && self.enabled[other]
+                })
+        })
+    }
 
-            scope.spawn(move || {
-                let sender = sender_clone;
-                let iter = iter_ref;
-                let mut cache = PatternCache::default();
+    // Enabled towels left once every candidate that can be is dropped
+    fn drop_greedily(&mut self) -> Vec<usize> {
+        let before = self.enabled.clone();
+        for towel in self.candidates.clone() {
+            self.enabled[towel] = !self.can_drop(towel);
+        }
+        let kept = (0..self.enabled.len())
+            .filter(|towel| self.enabled[*towel])
+            .collect();
+        self.enabled = before;
+        kept
+    }
 
-                let mut pattern_opt = iter.lock().unwrap().next();
-                loop {
-                    let Some(pattern) = pattern_opt else {
-                        break;
-                    };
-                    let possible = possible_patterns(pattern, towels, &mut cache);
-                    sender.send(possible).unwrap();
-                    pattern_opt = iter.lock().unwrap().next();
-                }
-            });
+    fn search(&mut self, depth: usize, kept: Vec<usize>) {
+        self.nodes += 1;
+        if kept.len() >= self.best.len() || self.nodes > self.node_limit {
+            return;
         }
+        let Some(&towel) = self.candidates.get(depth) else {
+            self.best = kept;
+            return;
+        };
 
-        std::mem::drop(sender);
+        if self.can_drop(towel) {
+            self.enabled[towel] = false;
+            self.search(depth + 1, kept.clone());
+            self.enabled[towel] = true;
+        }
 
-        receiver.into_iter().sum()
-    })
+        let mut kept = kept;
+        kept.push(towel);
+        self.search(depth + 1, kept);
+    }
 }
 
-fn possible_patterns<'a>(
+#[derive(Debug, Clone)]
+pub struct Arrangements<'a> {
+    towels: &'a Towels,
     pattern: &'a [u8],
-    towels: &Towels<'a>,
-    cache: &mut PatternCache<'a, usize>,
-) -> usize {
-    if pattern.is_empty() {
-        return 1;
-    }
-    let towels_with_stripe = match pattern[0] {
-        b'b' => &towels.black,
-        b'w' => &towels.white,
-        b'r' => &towels.red,
-        b'g' => &towels.green,
-        b'u' => &towels.blue,
-        _ => unreachable!("invalid towel stripe"),
-    };
-    towels_with_stripe
-        .iter()
-        .filter(|towel| pattern.starts_with(towel))
-        .map(|towel| {
-            if let Some(cached) = cache.get(&pattern[towel.len()..]) {
-                cached
-            } else {
-                let count = possible_patterns(&pattern[towel.len()..], towels, cache);
-                cache.insert(&pattern[towel.len()..], count);
-                count
-            }
-        })
-        .sum()
+    order: ArrangementOrder,
+    // Arrangements of `pattern[i..]`, by number of towels when ordering by
+    // fewest towels
+    counts: Vec<Vec<usize>>,
+    rank: usize,
+    total: usize,
 }
 
-#[derive(Debug, Default)]
-struct Towels<'a> {
-    black: Vec<&'a [u8]>,
-    white: Vec<&'a [u8]>,
-    red: Vec<&'a [u8]>,
-    green: Vec<&'a [u8]>,
-    blue: Vec<&'a [u8]>,
-}
+impl<'a> Arrangements<'a> {
+    // Arrangement at `rank`, choosing at each position the first towel whose
+    // arrangements contain it
+    fn unrank(&self, mut rank: usize) -> Vec<&'a [u8]> {
+        let towels = self.towels;
+        let mut left = match self.order {
+            ArrangementOrder::Lexicographic => 0,
+            ArrangementOrder::FewestTowels => {
+                let mut left = 0;
+                for (towels, count) in self.counts[0].iter().enumerate() {
+                    if rank < *count {
+                        left = towels;
+                        break;
+                    }
+                    rank -= count;
+                }
+                left
+            }
+        };
 
-#[derive(Debug, Default)]
-struct PatternCache<'a, T> {
-    cache: HashMap<&'a [u8], T>,
+        let mut start = 0;
+        let mut arrangement = Vec::new();
+        while start < self.pattern.len() {
+            for towel in towels.prefixes(&self.pattern[start..]) {
+                let next = start + towels.towels[towel].len();
+                let count = match self.order {
+                    ArrangementOrder::Lexicographic => self.counts[next][0],
+                    ArrangementOrder::FewestTowels => {
+                        self.counts[next].get(left - 1).copied().unwrap_or(0)
+                    }
+                };
+                if rank < count {
+                    arrangement.push(towels.towels[towel].as_slice());
+                    start = next;
+                    left = left.saturating_sub(1);
+                    break;
+                }
+                rank -= count;
+            }
+        }
+        arrangement
+    }
 }
 
-impl<'a, T: Copy + PartialEq + Debug> PatternCache<'a, T> {
-    fn get(&self, key: &[u8]) -> Option<T> {
-        self.cache.get(key).copied()
-    }
+impl<'a> Iterator for Arrangements<'a> {
+    type Item = Vec<&'a [u8]>;
 
-    fn insert(&mut self, key: &'a [u8], value: T) {
-        if let Some(exists) = self.cache.insert(key, value) {
-            assert_eq!(
-                exists, value,
-                "In case there is an attempt to insert on the same key, value should be equals"
-            );
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rank >= self.total {
+            return None;
         }
+        self.rank += 1;
+        Some(self.unrank(self.rank - 1))
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.rank = self.rank.saturating_add(n).min(self.total);
+        self.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.total - self.rank;
+        (len, Some(len))
     }
 }
+
+impl ExactSizeIterator for Arrangements<'_> {}
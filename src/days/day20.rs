@@ -6,14 +6,28 @@ use std::{
 use crate::{coord::Coord, maze::Maze, vec2d::Vec2d};
 
 pub fn part1(reader: impl Read) -> usize {
-    day_20_internal(reader, 2)
+    day_20_internal(reader, 2, 100)
 }
 
 pub fn part2(reader: impl Read) -> usize {
-    day_20_internal(reader, 20)
+    day_20_internal(reader, 20, 100)
 }
 
-fn day_20_internal(mut reader: impl Read, cheat_len: usize) -> usize {
+fn day_20_internal(reader: impl Read, cheat_len: usize, threshold: usize) -> usize {
+    find_cheats(reader, cheat_len, threshold).len()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Cheat {
+    pub start: Coord,
+    pub end: Coord,
+    // Picoseconds saved compared to following the track
+    pub savings: usize,
+}
+
+// Cheats lasting at most `cheat_len` picoseconds and saving at least
+// `threshold` picoseconds, sorted by start and end
+pub fn find_cheats(mut reader: impl Read, cheat_len: usize, threshold: usize) -> Vec<Cheat> {
     let mut data = Vec::with_capacity(100_000);
     reader.read_to_end(&mut data).unwrap();
 
@@ -22,20 +36,30 @@ fn day_20_internal(mut reader: impl Read, cheat_len: usize) -> usize {
     let (mut tile_cost_data, main_path) = maze.calculate_tile_scores();
     let tile_cost = Vec2d::new(tile_cost_data.as_mut_slice(), maze.width(), maze.height());
 
-    let cheats = cheat(&main_path, &tile_cost, cheat_len);
-
+    let mut cheats = cheat(&main_path, &tile_cost, cheat_len, threshold);
+    cheats.sort_unstable();
     cheats
-        .into_iter()
-        .map(|(k, v)| if k >= 100 { v } else { 0 })
-        .sum()
+}
+
+// Number of cheats for each amount of picoseconds saved
+pub fn savings_histogram(cheats: &[Cheat]) -> BTreeMap<usize, usize> {
+    let mut histogram = BTreeMap::new();
+    for cheat in cheats {
+        histogram
+            .entry(cheat.savings)
+            .and_modify(|count| *count += 1)
+            .or_insert(1);
+    }
+    histogram
 }
 
 fn cheat(
     main_path: &BTreeSet<Coord>,
     tile_cost: &Vec2d<usize>,
     cheat_len: usize,
-) -> BTreeMap<usize, usize> {
-    let mut map = BTreeMap::new();
+    threshold: usize,
+) -> Vec<Cheat> {
+    let mut cheats = Vec::new();
 
     let paralelism = std::thread::available_parallelism().unwrap().get();
 
@@ -52,7 +76,7 @@ fn cheat(
 
                 for tile in iter_ref {
                     sender
-                        .send(find_reachable(**tile, tile_cost, cheat_len))
+                        .send((**tile, find_reachable(**tile, tile_cost, cheat_len)))
                         .unwrap();
                 }
             });
@@ -60,16 +84,21 @@ fn cheat(
 
         std::mem::drop(sender);
 
-        for tile_reachables in receiver {
-            for (_, cost_cut) in tile_reachables {
-                map.entry(cost_cut)
-                    .and_modify(|count| *count += 1)
-                    .or_insert(1);
-            }
+        for (start, tile_reachables) in receiver {
+            cheats.extend(
+                tile_reachables
+                    .into_iter()
+                    .filter(|(_, cost_cut)| *cost_cut >= threshold)
+                    .map(|(end, savings)| Cheat {
+                        start,
+                        end,
+                        savings,
+                    }),
+            );
         }
     });
 
-    map
+    cheats
 }
 
 fn find_reachable(
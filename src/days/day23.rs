@@ -3,6 +3,10 @@ use std::{
     io::Read,
 };
 
+use crate::disjoint_set::DisjointSet;
+
+type Networks<'a> = DisjointSet<&'a [u8], ()>;
+
 pub fn part1(mut reader: impl Read) -> usize {
     let mut data = Vec::with_capacity(10_000);
     reader.read_to_end(&mut data).unwrap();
@@ -26,15 +30,36 @@ pub fn part2(mut reader: impl Read) -> String {
 
     let connections = parse_input(data.as_slice());
 
+    // Fully connected computers are all on the same network, so networks
+    // smaller than the largest group found can be skipped
+    let mut networks = Networks::new(|_, _| ());
+    for pc in connections.keys() {
+        networks.insert(pc, ());
+    }
+    for (pc, node_connections) in &connections {
+        for connected_to in node_connections {
+            networks.union(pc, connected_to);
+        }
+    }
+
     let mut computer_groups = Vec::with_capacity(500);
+    let mut largest = 0;
 
     connections.iter().for_each(|(pc, node_connections)| {
+        if networks.set_size(pc).unwrap() <= largest {
+            return;
+        }
+        let found = computer_groups.len();
         get_fully_connected(
             node_connections,
             &connections,
             vec![pc],
             &mut computer_groups,
         );
+        largest = computer_groups[found..]
+            .iter()
+            .map(Vec::len)
+            .fold(largest, usize::max);
     });
 
     computer_groups
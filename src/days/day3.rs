@@ -17,7 +17,6 @@ enum StateMachine {
 }
 
 fn collect_all_mul(string: &str, disable_conditionals: bool) -> u32 {
-    let mut chars = string.chars();
     let mut muls = 0;
 
     let mut state_machine = StateMachine::Start;
@@ -27,11 +26,7 @@ fn collect_all_mul(string: &str, disable_conditionals: bool) -> u32 {
 
     let mut enabled = true;
 
-    loop {
-        let Some(c) = chars.next() else {
-            break;
-        };
-
+    for c in string.chars() {
         state_machine = match (state_machine, c) {
             (StateMachine::Start, 'm') => StateMachine::ReadM,
             (StateMachine::ReadM, 'u') => StateMachine::ReadU,
@@ -1,6 +1,6 @@
 use std::io::Read;
 
-use crate::walker::Walker;
+use crate::walker::{PatrolLoop, Walker};
 
 pub fn part1(mut reader: impl Read) -> usize {
     let mut data = Vec::with_capacity(100_000);
@@ -17,5 +17,12 @@ pub fn part2(mut reader: impl Read) -> usize {
 
     Walker::from_data(data.as_slice())
         .unwrap()
-        .find_possible_loops2()
+        .find_possible_loops3()
+}
+
+pub fn patrol_report(mut reader: impl Read) -> Vec<PatrolLoop> {
+    let mut data = Vec::with_capacity(100_000);
+    reader.read_to_end(&mut data).unwrap();
+
+    Walker::from_data(data.as_slice()).unwrap().patrol_report()
 }
@@ -1,13 +1,21 @@
-use std::io::{BufReader, Read};
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    io::{BufRead, BufReader, Read},
+};
 
-fn build_disk_map(reader: impl Read) -> impl Iterator<Item = DiskMap> {
+fn build_disk_map(reader: impl BufRead) -> impl Iterator<Item = DiskMap> {
     let e: fn(u64, u64) -> DiskMap = DiskMap::File;
-    reader.bytes().enumerate().scan(e, |e, byte| {
-        let (i, byte) = (byte.0, byte.1.unwrap());
-        let d = e(u64::try_from(i).unwrap() / 2, u64::from(byte - b'0'));
-        *e = d.next();
-        Some(d)
-    })
+    reader
+        .bytes()
+        .filter(|byte| !byte.as_ref().is_ok_and(u8::is_ascii_whitespace))
+        .enumerate()
+        .scan(e, |e, byte| {
+            let (i, byte) = (byte.0, byte.1.unwrap());
+            let d = e(u64::try_from(i).unwrap() / 2, u64::from(byte - b'0'));
+            *e = d.next();
+            Some(d)
+        })
 }
 
 pub fn part1(reader: impl Read) -> u64 {
@@ -53,6 +61,15 @@ pub fn part1(reader: impl Read) -> u64 {
 }
 
 pub fn part2(reader: impl Read) -> u64 {
+    Disk::parse(reader)
+        .compact(CompactionStrategy::FirstFit)
+        .checksum
+}
+
+// The previous part 2, only public for the benches and tests comparing it
+// with `part2`
+#[doc(hidden)]
+pub fn part2_linear(reader: impl Read) -> u64 {
     let buf = BufReader::with_capacity(100_000, reader);
 
     let mut disk_map_indexes = build_disk_map(buf)
@@ -72,7 +89,10 @@ pub fn part2(reader: impl Read) -> u64 {
         };
         let front_size = loop {
             if front_i >= back_i {
-                back_i -= 1;
+                let Some(next_back_i) = back_i.checked_sub(1) else {
+                    break 'outer;
+                };
+                back_i = next_back_i;
                 front_i = 1;
                 continue 'outer;
             }
@@ -93,7 +113,7 @@ pub fn part2(reader: impl Read) -> u64 {
             }
         };
 
-        move_file_to_free(
+        back_i = move_file_to_free(
             &mut disk_map_indexes,
             front_i,
             back_i,
@@ -120,7 +140,7 @@ fn move_file_to_free(
     src: usize,
     front_size: u64,
     back_size: u64,
-) {
+) -> usize {
     let back = disk_map.remove(src);
     let additional_size = if let Some(DiskMap::Free(_, size2)) = disk_map.get(src) {
         *size2
@@ -130,10 +150,16 @@ fn move_file_to_free(
     if additional_size > 0 {
         disk_map.remove(src);
     }
-    if let Some(DiskMap::Free(_, size)) = disk_map.get_mut(src) {
+    let freed = if let Some((prev, DiskMap::Free(_, size))) = src
+        .checked_sub(1)
+        .filter(|prev| *prev > dst)
+        .and_then(|prev| disk_map.get_mut(prev).map(|disk_map| (prev, disk_map)))
+    {
         *size += back_size + additional_size;
+        prev
     } else {
         disk_map.insert(src, DiskMap::Free(0, back_size + additional_size));
+        src
     };
 
     if front_size == back_size {
@@ -149,13 +175,15 @@ fn move_file_to_free(
             panic!("Front must be Free");
         }
         disk_map.insert(dst, back);
+        return freed + 1;
     }
+
+    freed
 }
 
 #[derive(Debug, Clone, PartialEq)]
-enum DiskMap {
+pub enum DiskMap {
     File(u64, u64),
-    #[allow(dead_code)]
     Free(u64, u64),
 }
 
@@ -168,10 +196,372 @@ impl DiskMap {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompactionStrategy {
+    // Moves single blocks from the end of the disk into the leftmost free block
+    BlockWise,
+    // Moves whole files into the leftmost free span that fits them
+    FirstFit,
+    // Moves whole files into the smallest free span that fits them
+    BestFit,
+    // Packs whole files at the start of the disk, keeping their order
+    Defragment,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Compaction {
+    pub checksum: u64,
+    pub moves: usize,
+    // Id of the file on each block, `None` being free space
+    pub layout: Vec<Option<u64>>,
+}
+
+// (id, start, len)
+type FileExtent = (u64, usize, usize);
+// (start, len)
+type FreeExtent = (usize, usize);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Disk {
+    disk_map: Vec<DiskMap>,
+}
+
+impl Disk {
+    pub fn parse(reader: impl Read) -> Self {
+        let buf = BufReader::with_capacity(100_000, reader);
+        Self::from_disk_map(build_disk_map(buf))
+    }
+
+    pub fn from_disk_map(disk_map: impl IntoIterator<Item = DiskMap>) -> Self {
+        Self {
+            disk_map: disk_map.into_iter().collect(),
+        }
+    }
+
+    pub fn from_layout(layout: &[Option<u64>]) -> Self {
+        let mut files = 0;
+        let mut disk_map = Vec::new();
+
+        for run in layout.chunk_by(|lhs, rhs| lhs == rhs) {
+            let len = u64::try_from(run.len()).unwrap();
+            match run[0] {
+                Some(id) => {
+                    // Keeps files and free space alternating
+                    if matches!(disk_map.last(), Some(DiskMap::File(_, _))) {
+                        disk_map.push(DiskMap::Free(files - 1, 0));
+                    }
+                    disk_map.push(DiskMap::File(id, len));
+                    files += 1;
+                }
+                None => disk_map.push(DiskMap::Free(files.saturating_sub(1), len)),
+            }
+        }
+
+        Self { disk_map }
+    }
+
+    // The dense format can only hold files with sequential ids and spans
+    // shorter than 10 blocks
+    pub fn to_dense(&self) -> Option<String> {
+        self.disk_map
+            .iter()
+            .enumerate()
+            .map(|(i, disk_map)| {
+                let i = u64::try_from(i).unwrap();
+                match disk_map {
+                    DiskMap::File(id, len) if i % 2 == 0 && *id == i / 2 => Some(len),
+                    DiskMap::Free(_, len) if i % 2 == 1 => Some(len),
+                    _ => None,
+                }
+                .and_then(|len| u32::try_from(*len).ok())
+                .and_then(|len| char::from_digit(len, 10))
+            })
+            .collect()
+    }
+
+    pub fn disk_map(&self) -> &[DiskMap] {
+        &self.disk_map
+    }
+
+    pub fn size(&self) -> usize {
+        self.disk_map
+            .iter()
+            .map(|disk_map| match disk_map {
+                DiskMap::File(_, len) | DiskMap::Free(_, len) => usize::try_from(*len).unwrap(),
+            })
+            .sum()
+    }
+
+    pub fn layout(&self) -> Vec<Option<u64>> {
+        self.disk_map
+            .iter()
+            .flat_map(|disk_map| match disk_map {
+                DiskMap::File(id, len) => vec![Some(*id); usize::try_from(*len).unwrap()],
+                DiskMap::Free(_, len) => vec![None; usize::try_from(*len).unwrap()],
+            })
+            .collect()
+    }
+
+    pub fn compact(&self, strategy: CompactionStrategy) -> Compaction {
+        let (moves, layout) = match strategy {
+            CompactionStrategy::BlockWise => self.compact_blocks(),
+            CompactionStrategy::FirstFit => self.compact_files(FreeSpaceIndex::first_fit),
+            CompactionStrategy::BestFit => self.compact_files(FreeSpaceIndex::best_fit),
+            CompactionStrategy::Defragment => self.defragment(),
+        };
+
+        Compaction {
+            checksum: checksum(&layout),
+            moves,
+            layout,
+        }
+    }
+
+    fn extents(&self) -> (Vec<FileExtent>, Vec<FreeExtent>) {
+        let mut files = Vec::with_capacity(self.disk_map.len() / 2 + 1);
+        let mut free = Vec::with_capacity(self.disk_map.len() / 2);
+
+        let mut start = 0;
+        for disk_map in &self.disk_map {
+            match disk_map {
+                DiskMap::File(id, len) => {
+                    let len = usize::try_from(*len).unwrap();
+                    files.push((*id, start, len));
+                    start += len;
+                }
+                DiskMap::Free(_, len) => {
+                    let len = usize::try_from(*len).unwrap();
+                    free.push((start, len));
+                    start += len;
+                }
+            }
+        }
+
+        (files, free)
+    }
+
+    fn compact_blocks(&self) -> (usize, Vec<Option<u64>>) {
+        let mut layout = self.layout();
+        let mut moves = 0;
+
+        let mut front = 0;
+        let mut back = layout.len();
+        loop {
+            while front < back && layout[front].is_some() {
+                front += 1;
+            }
+            while back > front && layout[back - 1].is_none() {
+                back -= 1;
+            }
+            if back - front < 2 {
+                break;
+            }
+            layout.swap(front, back - 1);
+            moves += 1;
+        }
+
+        (moves, layout)
+    }
+
+    // `pick` receives the free space, the length of the file, and the start of
+    // the file, and returns the free span the file moves to
+    fn compact_files(
+        &self,
+        pick: impl Fn(&FreeSpaceIndex, usize, usize) -> Option<FreeExtent>,
+    ) -> (usize, Vec<Option<u64>>) {
+        let (mut files, free) = self.extents();
+        let mut free_space = FreeSpaceIndex::new(&free);
+        let mut moves = 0;
+
+        // Space freed by a file is always to the right of the files that are
+        // left to move, so it never needs to be given back to `free_space`
+        for (_, start, len) in files.iter_mut().rev().filter(|(_, _, len)| *len > 0) {
+            if let Some(span) = pick(&free_space, *len, *start) {
+                free_space.take(span, *len);
+                *start = span.0;
+                moves += 1;
+            }
+        }
+
+        (moves, layout_from_files(&files, self.size()))
+    }
+
+    fn defragment(&self) -> (usize, Vec<Option<u64>>) {
+        let (mut files, _) = self.extents();
+        let mut moves = 0;
+
+        let mut next = 0;
+        for (_, start, len) in files.iter_mut() {
+            if *start != next {
+                *start = next;
+                moves += 1;
+            }
+            next += *len;
+        }
+
+        (moves, layout_from_files(&files, self.size()))
+    }
+}
+
+// Free spans bucketed by length, each bucket being a min-heap of the starts
+// of the spans
+#[derive(Debug)]
+struct FreeSpaceIndex {
+    buckets: Vec<BinaryHeap<Reverse<usize>>>,
+}
+
+impl FreeSpaceIndex {
+    fn new(free: &[FreeExtent]) -> Self {
+        let longest = free.iter().map(|(_, len)| *len).max().unwrap_or_default();
+
+        let mut buckets = vec![BinaryHeap::new(); longest + 1];
+        for (start, len) in free.iter().filter(|(_, len)| *len > 0) {
+            buckets[*len].push(Reverse(*start));
+        }
+
+        Self { buckets }
+    }
+
+    fn leftmost_spans(&self, len: usize, before: usize) -> impl Iterator<Item = FreeExtent> + '_ {
+        self.buckets
+            .iter()
+            .enumerate()
+            .skip(len)
+            .filter_map(|(len, bucket)| bucket.peek().map(|Reverse(start)| (*start, len)))
+            .filter(move |(start, _)| *start < before)
+    }
+
+    fn first_fit(&self, len: usize, before: usize) -> Option<FreeExtent> {
+        self.leftmost_spans(len, before).min()
+    }
+
+    fn best_fit(&self, len: usize, before: usize) -> Option<FreeExtent> {
+        self.leftmost_spans(len, before).next()
+    }
+
+    // `span` must have come from `first_fit` or `best_fit`
+    fn take(&mut self, (start, span_len): FreeExtent, len: usize) {
+        self.buckets[span_len].pop();
+        if span_len > len {
+            self.buckets[span_len - len].push(Reverse(start + len));
+        }
+    }
+}
+
+// Renders a layout like `00...111...2`, files with ids past 9 can't be rendered
+pub fn render_layout(layout: &[Option<u64>]) -> Option<String> {
+    layout
+        .iter()
+        .map(|block| match block {
+            Some(id) => u32::try_from(*id)
+                .ok()
+                .and_then(|id| char::from_digit(id, 10)),
+            None => Some('.'),
+        })
+        .collect()
+}
+
+pub fn parse_layout(reader: impl Read) -> Option<Vec<Option<u64>>> {
+    let buf = BufReader::with_capacity(100_000, reader);
+
+    buf.bytes()
+        .map(|block| block.unwrap())
+        .filter(|block| !block.is_ascii_whitespace())
+        .map(|block| match block {
+            b'.' => Some(None),
+            b'0'..=b'9' => Some(Some(u64::from(block - b'0'))),
+            _ => None,
+        })
+        .collect()
+}
+
+fn layout_from_files(files: &[FileExtent], size: usize) -> Vec<Option<u64>> {
+    let mut layout = vec![None; size];
+    for (id, start, len) in files {
+        layout[*start..*start + *len].fill(Some(*id));
+    }
+    layout
+}
+
+fn checksum(layout: &[Option<u64>]) -> u64 {
+    layout
+        .iter()
+        .enumerate()
+        .map(|(i, id)| u64::try_from(i).unwrap() * id.unwrap_or(0))
+        .sum()
+}
+
 #[cfg(test)]
 mod tests {
+    use proptest::prelude::*;
+
     use super::*;
 
+    // Files can be empty, and the map can end with free space
+    fn dense_disk_map() -> impl Strategy<Value = String> {
+        (
+            prop::collection::vec((0..=9u8, 0..=9u8), 0..50),
+            0..=9u8,
+            prop::option::of(0..=9u8),
+        )
+            .prop_map(|(spans, last, trailing)| {
+                spans
+                    .into_iter()
+                    .flat_map(|(file, free)| [file, free])
+                    .chain([last])
+                    .chain(trailing)
+                    .map(|len| char::from(b'0' + len))
+                    .collect()
+            })
+    }
+
+    fn layout() -> impl Strategy<Value = Vec<Option<u64>>> {
+        prop::collection::vec(prop::option::of(0..10u64), 0..200)
+    }
+
+    proptest! {
+        #[test]
+        fn dense_round_trip(dense in dense_disk_map()) {
+            let disk = Disk::parse(dense.as_bytes());
+            prop_assert_eq!(disk.to_dense(), Some(dense));
+
+            // Empty files have no block, so they can't be found back from the
+            // layout, and neither can an empty trailing free span
+            let from_layout = Disk::from_layout(&disk.layout());
+            prop_assert_eq!(from_layout.layout(), disk.layout());
+            let empty_file = disk
+                .disk_map()
+                .iter()
+                .any(|disk_map| matches!(disk_map, DiskMap::File(_, 0)));
+            if !empty_file && !matches!(disk.disk_map().last(), Some(DiskMap::Free(_, 0))) {
+                prop_assert_eq!(from_layout, disk);
+            }
+        }
+
+        #[test]
+        fn layout_round_trip(layout in layout()) {
+            prop_assert_eq!(&Disk::from_layout(&layout).layout(), &layout);
+
+            let rendered = render_layout(&layout).unwrap();
+            prop_assert_eq!(parse_layout(rendered.as_bytes()), Some(layout));
+        }
+
+        #[test]
+        fn compacted_layout_round_trip(dense in dense_disk_map()) {
+            let disk = Disk::parse(dense.as_bytes());
+            for strategy in [
+                CompactionStrategy::BlockWise,
+                CompactionStrategy::FirstFit,
+                CompactionStrategy::BestFit,
+                CompactionStrategy::Defragment,
+            ] {
+                let compaction = disk.compact(strategy);
+                let compacted = Disk::from_layout(&compaction.layout);
+                prop_assert_eq!(&compacted.layout(), &compaction.layout);
+            }
+        }
+    }
+
     #[test]
     fn test_move() {
         let mut test = vec![
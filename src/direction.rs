@@ -28,6 +28,15 @@ impl Direction {
         }
     }
 
+    pub fn reverse(self) -> Self {
+        match self {
+            Self::North => Self::South,
+            Self::South => Self::North,
+            Self::East => Self::West,
+            Self::West => Self::East,
+        }
+    }
+
     pub fn step(&self, coord: Coord) -> Coord {
         match self {
             Direction::North => coord - (1, 0),
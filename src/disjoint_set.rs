@@ -0,0 +1,184 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    hash::Hash,
+};
+
+// Union-find with path compression and union by size, every set carries a
+// value that is combined with `merge` when two sets are joined. `merge` must be
+// associative, as the order in which sets are joined is not kept.
+#[derive(Debug)]
+pub struct DisjointSet<K, V, F = fn(V, V) -> V> {
+    indexes: HashMap<K, usize>,
+    keys: Vec<K>,
+    parents: Vec<usize>,
+    sizes: Vec<usize>,
+    // Only roots have values
+    values: Vec<Option<V>>,
+    merge: F,
+}
+
+impl<K, V, F> DisjointSet<K, V, F>
+where
+    K: Hash + Eq + Clone,
+    F: Fn(V, V) -> V,
+{
+    pub fn new(merge: F) -> Self {
+        Self {
+            indexes: HashMap::new(),
+            keys: Vec::new(),
+            parents: Vec::new(),
+            sizes: Vec::new(),
+            values: Vec::new(),
+            merge,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.indexes.contains_key(key)
+    }
+
+    // Returns `false`, and leaves the set untouched, if `key` was already present
+    pub fn insert(&mut self, key: K, value: V) -> bool {
+        match self.indexes.entry(key) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                let index = self.keys.len();
+                self.keys.push(entry.key().clone());
+                entry.insert(index);
+                self.parents.push(index);
+                self.sizes.push(1);
+                self.values.push(Some(value));
+                true
+            }
+        }
+    }
+
+    fn find_index(&mut self, key: &K) -> Option<usize> {
+        let index = *self.indexes.get(key)?;
+
+        let mut root = index;
+        while self.parents[root] != root {
+            root = self.parents[root];
+        }
+
+        let mut current = index;
+        while self.parents[current] != root {
+            let next = self.parents[current];
+            self.parents[current] = root;
+            current = next;
+        }
+
+        Some(root)
+    }
+
+    // Key of the root of the set containing `key`
+    pub fn find(&mut self, key: &K) -> Option<&K> {
+        self.find_index(key).map(|root| &self.keys[root])
+    }
+
+    pub fn same_set(&mut self, lhs: &K, rhs: &K) -> bool {
+        match (self.find_index(lhs), self.find_index(rhs)) {
+            (Some(lhs), Some(rhs)) => lhs == rhs,
+            _ => false,
+        }
+    }
+
+    // Value of the set containing `key`
+    pub fn value(&mut self, key: &K) -> Option<&V> {
+        self.find_index(key)
+            .and_then(|root| self.values[root].as_ref())
+    }
+
+    pub fn set_size(&mut self, key: &K) -> Option<usize> {
+        self.find_index(key).map(|root| self.sizes[root])
+    }
+
+    // Joins the sets of `lhs` and `rhs`, the new value being
+    // `merge(lhs_value, rhs_value)`.
+    // Returns `false` if either key is missing or both are already in the same set.
+    pub fn union(&mut self, lhs: &K, rhs: &K) -> bool {
+        let (Some(lhs), Some(rhs)) = (self.find_index(lhs), self.find_index(rhs)) else {
+            return false;
+        };
+        if lhs == rhs {
+            return false;
+        }
+
+        let lhs_value = self.values[lhs].take().unwrap();
+        let rhs_value = self.values[rhs].take().unwrap();
+        let value = (self.merge)(lhs_value, rhs_value);
+
+        let (root, child) = if self.sizes[lhs] >= self.sizes[rhs] {
+            (lhs, rhs)
+        } else {
+            (rhs, lhs)
+        };
+        self.parents[child] = root;
+        self.sizes[root] += self.sizes[child];
+        self.values[root] = Some(value);
+
+        true
+    }
+
+    // Root key and value of every set
+    pub fn sets(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.values
+            .iter()
+            .enumerate()
+            .filter_map(|(index, value)| value.as_ref().map(|value| (&self.keys[index], value)))
+    }
+
+    pub fn into_values(self) -> impl Iterator<Item = V> {
+        self.values.into_iter().flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn union_merges_values() {
+        let mut set = DisjointSet::new(|lhs: Vec<u8>, rhs: Vec<u8>| [lhs, rhs].concat());
+        for key in 0..6u8 {
+            assert!(set.insert(key, vec![key]));
+        }
+        assert!(!set.insert(0, vec![]));
+
+        assert!(set.union(&0, &1));
+        assert!(set.union(&2, &3));
+        assert!(set.union(&1, &3));
+        assert!(!set.union(&0, &2));
+        assert!(!set.union(&0, &6));
+
+        assert!(set.same_set(&0, &3));
+        assert!(!set.same_set(&0, &4));
+        assert_eq!(set.set_size(&2), Some(4));
+        assert_eq!(set.value(&3), Some(&vec![0, 1, 2, 3]));
+        assert_eq!(set.sets().count(), 3);
+    }
+
+    #[test]
+    fn union_by_size() {
+        let mut set = DisjointSet::new(|lhs: u32, rhs: u32| lhs + rhs);
+        for key in 0..4 {
+            set.insert(key, 1);
+        }
+        set.union(&0, &1);
+        let root = *set.find(&0).unwrap();
+        // The single element set is attached to the bigger one
+        set.union(&2, &0);
+        assert_eq!(set.find(&2), Some(&root));
+        assert_eq!(set.value(&2), Some(&3));
+
+        assert_eq!(set.into_values().collect::<Vec<_>>(), vec![3, 1]);
+    }
+}
@@ -0,0 +1,245 @@
+use crate::{coord::Coord, vec2d::Vec2d};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+    Four,
+    Eight,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComponentStats {
+    pub area: usize,
+    // Edges shared with another component or with the border of the grid,
+    // always counted on the 4 sides of a cell
+    pub perimeter: usize,
+    // Top left and bottom right corners, both inclusive
+    pub bounding_box: (Coord, Coord),
+    // First cell of the component in row major order
+    pub first: Coord,
+}
+
+#[derive(Debug, Clone)]
+pub struct Labelling {
+    width: usize,
+    height: usize,
+    labels: Vec<u32>,
+    components: Vec<ComponentStats>,
+}
+
+impl Labelling {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn label(&self, coord: Coord) -> usize {
+        index(self.labels[coord.row * self.width + coord.column])
+    }
+
+    // Labels in row major order, components are numbered in the order their
+    // first cell is met
+    pub fn labels(&self) -> &[u32] {
+        &self.labels
+    }
+
+    pub fn components(&self) -> &[ComponentStats] {
+        &self.components
+    }
+
+    pub fn component_count(&self) -> usize {
+        self.components.len()
+    }
+}
+
+fn index(label: u32) -> usize {
+    usize::try_from(label).unwrap()
+}
+
+// Union-find over the provisional labels. A flat vector is used instead of
+// `DisjointSet` as there can be as many provisional labels as cells.
+struct Equivalences {
+    parents: Vec<u32>,
+}
+
+impl Equivalences {
+    fn push(&mut self) -> u32 {
+        let label = u32::try_from(self.parents.len()).expect("Too many labels.");
+        self.parents.push(label);
+        label
+    }
+
+    fn find(&mut self, mut label: u32) -> u32 {
+        while self.parents[index(label)] != label {
+            let parent = self.parents[index(label)];
+            self.parents[index(label)] = self.parents[index(parent)];
+            label = parent;
+        }
+        label
+    }
+
+    // The smallest label is kept as root, so roots are met in row major order
+    fn union(&mut self, lhs: u32, rhs: u32) -> u32 {
+        let lhs = self.find(lhs);
+        let rhs = self.find(rhs);
+        let (root, child) = if lhs <= rhs { (lhs, rhs) } else { (rhs, lhs) };
+        self.parents[index(child)] = root;
+        root
+    }
+}
+
+// Two-pass labelling: the first pass gives each cell the label of an already
+// visited neighbour, recording equivalences when several of them match, the
+// second pass resolves the labels and gathers the statistics.
+// `same` is expected to be symmetric.
+pub fn label_components<T>(
+    grid: &Vec2d<T>,
+    connectivity: Connectivity,
+    same: impl Fn(&T, &T) -> bool,
+) -> Labelling {
+    let width = grid.width();
+    let height = grid.height();
+
+    let mut labels = vec![0u32; width * height];
+    let mut equivalences = Equivalences {
+        parents: Vec::new(),
+    };
+
+    for row in 0..height {
+        for column in 0..width {
+            let current = &grid[Coord::new(row, column)];
+            let west = column.checked_sub(1).map(|column| (row, column));
+            let north = row.checked_sub(1).map(|row| (row, column));
+            let (north_west, north_east) = match connectivity {
+                Connectivity::Four => (None, None),
+                Connectivity::Eight => (
+                    row.checked_sub(1).zip(column.checked_sub(1)),
+                    row.checked_sub(1)
+                        .map(|row| (row, column + 1))
+                        .filter(|(_, column)| *column < width),
+                ),
+            };
+
+            let mut label = None;
+            for (row, column) in [west, north_west, north, north_east].into_iter().flatten() {
+                if !same(current, &grid[Coord::new(row, column)]) {
+                    continue;
+                }
+                let neighbour = labels[row * width + column];
+                label = Some(match label {
+                    None => equivalences.find(neighbour),
+                    Some(label) => equivalences.union(label, neighbour),
+                });
+            }
+
+            labels[row * width + column] = label.unwrap_or_else(|| equivalences.push());
+        }
+    }
+
+    let mut finals = vec![u32::MAX; equivalences.parents.len()];
+    let mut components: Vec<ComponentStats> = Vec::new();
+
+    for row in 0..height {
+        for column in 0..width {
+            let cell = row * width + column;
+            let root = index(equivalences.find(labels[cell]));
+            if finals[root] == u32::MAX {
+                finals[root] = u32::try_from(components.len()).unwrap();
+                let coord = Coord::new(row, column);
+                components.push(ComponentStats {
+                    area: 0,
+                    perimeter: 0,
+                    bounding_box: (coord, coord),
+                    first: coord,
+                });
+            }
+            let label = finals[root];
+            labels[cell] = label;
+
+            let component = &mut components[index(label)];
+            component.area += 1;
+            component.perimeter += 4;
+            if column > 0 && labels[cell - 1] == label {
+                component.perimeter -= 2;
+            }
+            if row > 0 && labels[cell - width] == label {
+                component.perimeter -= 2;
+            }
+            let (top_left, bottom_right) = &mut component.bounding_box;
+            top_left.column = top_left.column.min(column);
+            bottom_right.row = row;
+            bottom_right.column = bottom_right.column.max(column);
+        }
+    }
+
+    Labelling {
+        width,
+        height,
+        labels,
+        components,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(data: &str) -> (Vec<u8>, usize, usize) {
+        let lines = data.lines().collect::<Vec<_>>();
+        (lines.concat().into_bytes(), lines[0].len(), lines.len())
+    }
+
+    #[test]
+    fn four_way() {
+        let (mut data, width, height) = grid("AAAA\nBBCD\nBBCC\nEEEC");
+        let labelling = label_components(
+            &Vec2d::new(&mut data, width, height),
+            Connectivity::Four,
+            |lhs, rhs| lhs == rhs,
+        );
+
+        assert_eq!(labelling.component_count(), 5);
+        assert_eq!(
+            labelling
+                .components()
+                .iter()
+                .map(|component| (component.area, component.perimeter))
+                .collect::<Vec<_>>(),
+            vec![(4, 10), (4, 8), (4, 10), (1, 4), (3, 8)]
+        );
+        assert_eq!(
+            labelling.components()[2].bounding_box,
+            (Coord::new(1, 2), Coord::new(3, 3))
+        );
+        assert_eq!(labelling.label(Coord::new(3, 3)), 2);
+        assert_eq!(labelling.label(Coord::new(1, 3)), 3);
+    }
+
+    #[test]
+    fn eight_way() {
+        // The diagonal of # only joins with 8-connectivity, the U shape needs
+        // the labels of both of its branches to be merged
+        let (mut data, width, height) = grid("#..#.#\n.#.#.#\n..##.#\n...###");
+        let mut grid = Vec2d::new(&mut data, width, height);
+
+        let four = label_components(&grid, Connectivity::Four, |lhs, rhs| lhs == rhs);
+        let eight = label_components(&grid, Connectivity::Eight, |lhs, rhs| lhs == rhs);
+
+        assert_eq!(four.component_count(), 6);
+        assert_eq!(eight.component_count(), 3);
+        assert_eq!(eight.components()[0].area, 12);
+        assert_eq!(eight.label(Coord::new(0, 0)), eight.label(Coord::new(0, 5)));
+        assert_eq!(
+            eight.components()[1].bounding_box,
+            (Coord::new(0, 0), Coord::new(3, 2))
+        );
+
+        grid[Coord::new(1, 1)] = b'.';
+        let eight = label_components(&grid, Connectivity::Eight, |lhs, rhs| lhs == rhs);
+        assert_eq!(eight.component_count(), 4);
+        assert_eq!(eight.components()[0].first, Coord::new(0, 0));
+        assert_eq!(eight.components()[0].area, 1);
+    }
+}
@@ -1,9 +1,13 @@
+pub mod bit_set;
 pub mod coord;
 mod days;
 pub mod direction;
+pub mod disjoint_set;
+pub mod labelling;
 pub mod maze;
 pub mod skip_at;
 pub mod vec2d;
 pub mod walker;
+pub mod xorshift;
 
 pub use days::*;
@@ -1,6 +1,17 @@
-use std::{collections::BTreeSet, sync::Mutex};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    num::NonZeroUsize,
+};
 
-use crate::direction::Direction;
+use crate::{bit_set::BitSet, coord::Coord, direction::Direction};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatrolLoop {
+    pub obstacle: (usize, usize),
+    // Index of the first step of the cycle, the start position being step 0
+    pub entered_at: usize,
+    pub cycle: Vec<(Direction, usize, usize)>,
+}
 
 #[derive(Debug, Clone)]
 pub struct Walker<'a> {
@@ -48,47 +59,41 @@ impl<'a> Walker<'a> {
     pub fn find_possible_loops2(self) -> usize {
         let start = (self.row, self.column);
         let original = self.clone();
-        let mutex = Mutex::new(self);
 
-        std::thread::scope(|scope| {
-            let mut obstacles = BTreeSet::new();
-            obstacles.insert(start);
-
-            let (sender, receiver) = std::sync::mpsc::channel();
-
-            for _ in 0..std::thread::available_parallelism().unwrap().get() {
-                let sender_clone = sender.clone();
-                let mutex_ref = &mutex;
-                scope.spawn(|| {
-                    let sender = sender_clone;
-
-                    loop {
-                        let opt_obstacle = mutex_ref
-                            .lock()
-                            .unwrap()
-                            .next()
-                            .map(|(_, row, column)| (row, column));
-                        let Some(obstacle) = opt_obstacle else {
-                            return;
-                        };
-
-                        let mut iter = original.clone();
-                        iter.extra_obstacles = [obstacle].into_iter().collect();
-
-                        if iter.is_loop() {
-                            sender.send(obstacle).unwrap();
-                        }
-                    }
-                });
-            }
+        let candidates = self
+            .map(|(_, row, column)| (row, column))
+            .filter(|obstacle| *obstacle != start)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
 
-            std::mem::drop(sender);
+        let threads = std::thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        let chunk_size = candidates.len().div_ceil(threads).max(1);
 
-            for obstacle in receiver {
-                obstacles.insert(obstacle);
-            }
+        std::thread::scope(|scope| {
+            let workers = candidates
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    let original = &original;
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .copied()
+                            .filter(|obstacle| {
+                                let mut divert = original.clone();
+                                divert.extra_obstacles = [*obstacle].into_iter().collect();
+                                divert.is_loop()
+                            })
+                            .collect::<BTreeSet<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
 
-            obstacles.len() - 1
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect::<BTreeSet<_>>()
+                .len()
         })
     }
 
@@ -113,6 +118,134 @@ impl<'a> Walker<'a> {
         obstacles.len() - 1
     }
 
+    pub fn find_possible_loops3(self) -> usize {
+        let jump_table = JumpTable::new(&self.lines);
+        let mut visited = BitSet::new(jump_table.cells());
+        let mut states = BitSet::new(jump_table.states());
+
+        let mut previous = Coord::new(self.row, self.column);
+        visited.insert(jump_table.cell_index(previous));
+
+        let mut loops = 0;
+        for (direction, row, column) in self {
+            let obstacle = Coord::new(row, column);
+            // An obstacle on an already visited cell would have
+            // stopped the guard earlier on the path
+            if visited.insert(jump_table.cell_index(obstacle))
+                && jump_table.is_loop(previous, direction, obstacle, &mut states)
+            {
+                loops += 1;
+            }
+            previous = obstacle;
+        }
+
+        loops
+    }
+
+    pub fn patrol_report(self) -> Vec<PatrolLoop> {
+        let start = (self.row, self.column);
+        let original = self.clone();
+
+        self.map(|(_, row, column)| (row, column))
+            .filter(|obstacle| *obstacle != start)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .filter_map(|obstacle| {
+                let mut divert = original.clone();
+                divert.extra_obstacles = [obstacle].into_iter().collect();
+
+                let (mut steps, entered_at) = divert.walk_until_loop();
+                entered_at.map(|entered_at| {
+                    // Last step is the repeated one
+                    steps.pop();
+                    PatrolLoop {
+                        obstacle,
+                        entered_at,
+                        cycle: steps.split_off(entered_at),
+                    }
+                })
+            })
+            .collect()
+    }
+
+    pub fn render_route(&self, obstacle: Option<(usize, usize)>) -> String {
+        let start = (self.row, self.column);
+
+        let mut walker = self.clone();
+        walker.extra_obstacles.extend(obstacle);
+        let (steps, _) = walker.walk_until_loop();
+
+        let height = self
+            .lines
+            .iter()
+            .take_while(|line| !line.is_empty())
+            .count();
+        let mut tiles = self.lines[..height]
+            .iter()
+            .map(|line| line.to_vec())
+            .collect::<Vec<_>>();
+
+        // (vertical, horizontal)
+        let mut marks = tiles
+            .iter()
+            .map(|line| vec![(false, false); line.len()])
+            .collect::<Vec<_>>();
+        let mut mark = |direction: Direction, row: usize, column: usize| {
+            let (vertical, horizontal) = &mut marks[row][column];
+            match direction {
+                Direction::North | Direction::South => *vertical = true,
+                Direction::East | Direction::West => *horizontal = true,
+            }
+        };
+        for (direction, row, column) in steps.iter().copied() {
+            mark(direction, row, column);
+        }
+        // Turns happen on the tile the guard is leaving
+        for window in steps.windows(2) {
+            let (_, row, column) = window[0];
+            let (direction, _, _) = window[1];
+            mark(direction, row, column);
+        }
+
+        for (row, line) in tiles.iter_mut().enumerate() {
+            for (column, tile) in line.iter_mut().enumerate() {
+                if (row, column) == start || *tile == b'#' {
+                    continue;
+                }
+                *tile = match marks[row][column] {
+                    (true, true) => b'+',
+                    (true, false) => b'|',
+                    (false, true) => b'-',
+                    (false, false) => *tile,
+                };
+            }
+        }
+        if let Some((row, column)) = obstacle {
+            tiles[row][column] = b'O';
+        }
+
+        String::from_utf8(tiles.join(&b'\n')).unwrap()
+    }
+
+    // Returns every step, starting with the start position, and the index of
+    // the first occurrence of the last step if the walk ends in a loop
+    fn walk_until_loop(self) -> (Vec<(Direction, usize, usize)>, Option<usize>) {
+        let first = (self.direction, self.row, self.column);
+        let mut steps = vec![first];
+        let mut seen = BTreeMap::new();
+        seen.insert(first, 0);
+
+        for step in self {
+            let index = steps.len();
+            steps.push(step);
+            if let Some(entered_at) = seen.insert(step, index) {
+                return (steps, Some(entered_at));
+            }
+        }
+
+        (steps, None)
+    }
+
     #[inline(always)]
     fn is_obstacle(&self, row: usize, column: usize) -> bool {
         let line = self.lines[row];
@@ -182,3 +315,144 @@ impl Iterator for Walker<'_> {
         }
     }
 }
+
+#[derive(Debug, Clone)]
+struct JumpTable {
+    width: usize,
+    height: usize,
+    // Where the guard stops when walking from a cell in a direction,
+    // `None` if the guard leaves the map
+    stops: Vec<Option<Coord>>,
+}
+
+impl JumpTable {
+    fn new(lines: &[&[u8]]) -> Self {
+        let height = lines.iter().take_while(|line| !line.is_empty()).count();
+        let width = lines[0].len();
+
+        let mut jump_table = Self {
+            width,
+            height,
+            stops: vec![None; width * height * 4],
+        };
+
+        let is_obstacle = |coord: Coord| lines[coord.row][coord.column] == b'#';
+        for column in 0..width {
+            jump_table.fill(
+                Direction::North,
+                (0..height).map(|row| Coord::new(row, column)),
+                is_obstacle,
+            );
+            jump_table.fill(
+                Direction::South,
+                (0..height).rev().map(|row| Coord::new(row, column)),
+                is_obstacle,
+            );
+        }
+        for row in 0..height {
+            jump_table.fill(
+                Direction::West,
+                (0..width).map(|column| Coord::new(row, column)),
+                is_obstacle,
+            );
+            jump_table.fill(
+                Direction::East,
+                (0..width).rev().map(|column| Coord::new(row, column)),
+                is_obstacle,
+            );
+        }
+
+        jump_table
+    }
+
+    // `line` must be ordered against `direction`, so that the obstacles
+    // are seen before the cells that would bump into them
+    fn fill(
+        &mut self,
+        direction: Direction,
+        line: impl Iterator<Item = Coord>,
+        is_obstacle: impl Fn(Coord) -> bool,
+    ) {
+        let mut stop = None;
+        let mut after_obstacle = false;
+        for coord in line {
+            if is_obstacle(coord) {
+                after_obstacle = true;
+            } else {
+                if after_obstacle {
+                    stop = Some(coord);
+                    after_obstacle = false;
+                }
+                let index = self.state_index(direction, coord);
+                self.stops[index] = stop;
+            }
+        }
+    }
+
+    fn cells(&self) -> usize {
+        self.width * self.height
+    }
+
+    fn states(&self) -> usize {
+        self.cells() * 4
+    }
+
+    fn cell_index(&self, coord: Coord) -> usize {
+        coord.row * self.width + coord.column
+    }
+
+    fn state_index(&self, direction: Direction, coord: Coord) -> usize {
+        direction as usize * self.cells() + self.cell_index(coord)
+    }
+
+    fn jump(&self, direction: Direction, coord: Coord, obstacle: Coord) -> Option<Coord> {
+        let stop = self.stops[self.state_index(direction, coord)];
+
+        let blocked = match direction {
+            Direction::North => {
+                obstacle.column == coord.column
+                    && obstacle.row < coord.row
+                    && stop.is_none_or(|stop| obstacle.row >= stop.row)
+            }
+            Direction::South => {
+                obstacle.column == coord.column
+                    && obstacle.row > coord.row
+                    && stop.is_none_or(|stop| obstacle.row <= stop.row)
+            }
+            Direction::East => {
+                obstacle.row == coord.row
+                    && obstacle.column > coord.column
+                    && stop.is_none_or(|stop| obstacle.column <= stop.column)
+            }
+            Direction::West => {
+                obstacle.row == coord.row
+                    && obstacle.column < coord.column
+                    && stop.is_none_or(|stop| obstacle.column >= stop.column)
+            }
+        };
+
+        if blocked {
+            Some(direction.reverse().step(obstacle))
+        } else {
+            stop
+        }
+    }
+
+    fn is_loop(
+        &self,
+        mut coord: Coord,
+        mut direction: Direction,
+        obstacle: Coord,
+        states: &mut BitSet,
+    ) -> bool {
+        states.clear();
+        while let Some(stop) = self.jump(direction, coord, obstacle) {
+            direction = direction.turn_right();
+            if !states.insert(self.state_index(direction, stop)) {
+                return true;
+            }
+            coord = stop;
+        }
+        false
+    }
+}
@@ -0,0 +1,8 @@
+// Xorshift generator, for benches and tests that need the same pseudo random
+// data on every run. `state` must not be 0.
+pub fn xorshift(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
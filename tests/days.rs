@@ -1,4 +1,21 @@
-use aoc2024::coord::Coord;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use num_bigint::BigUint;
+
+use aoc2024::{
+    coord::Coord,
+    day11::{EvolveError, StoneRules, StoneTransitions},
+    day13::{parse_claw_machines, ClawError, ClawMachine, ClawSolution, Machine, Solution},
+    day14::{Frame, Simulation},
+    day15::{parse_puzzle, MoveSearch, Step},
+    day18::FallingBytes,
+    day19::{ArrangementOrder, TowelStatus, Towels},
+    day20::{find_cheats, savings_histogram, Cheat},
+    day9::{parse_layout, render_layout, CompactionStrategy, Disk},
+    direction::Direction,
+    walker::Walker,
+    xorshift::xorshift,
+};
 
 #[test]
 fn day1() {
@@ -98,6 +115,40 @@ fn day6() {
 ......#..."#;
     assert_eq!(aoc2024::day6::part1(data.as_bytes()), 41);
     assert_eq!(aoc2024::day6::part2(data.as_bytes()), 6);
+
+    let walker = Walker::from_data(data.as_bytes()).unwrap();
+    assert_eq!(walker.clone().find_possible_loops(), 6);
+    assert_eq!(
+        walker.clone().find_possible_loops2(),
+        walker.clone().find_possible_loops()
+    );
+    assert_eq!(walker.clone().find_possible_loops3(), 6);
+
+    let report = aoc2024::day6::patrol_report(data.as_bytes());
+    assert_eq!(
+        report
+            .iter()
+            .map(|patrol_loop| patrol_loop.obstacle)
+            .collect::<Vec<_>>(),
+        vec![(6, 3), (7, 6), (7, 7), (8, 1), (8, 3), (9, 7)]
+    );
+    assert_eq!(report[0].entered_at, 1);
+    assert_eq!(report[0].cycle.len(), 18);
+    assert_eq!(report[0].cycle[0], (Direction::North, 5, 4));
+    assert_eq!(report[0].cycle[17], (Direction::West, 6, 4));
+    assert_eq!(
+        walker.render_route(Some((6, 3))),
+        r#"....#.....
+....+---+#
+....|...|.
+..#.|...|.
+....|..#|.
+....|...|.
+.#.O^---+.
+........#.
+#.........
+......#..."#
+    );
 }
 
 #[test]
@@ -138,6 +189,62 @@ fn day9() {
     let data = r#"2333133121414131402"#;
     assert_eq!(aoc2024::day9::part1(data.as_bytes()), 1928);
     assert_eq!(aoc2024::day9::part2(data.as_bytes()), 2858);
+    assert_eq!(aoc2024::day9::part2_linear(data.as_bytes()), 2858);
+
+    let layout = |layout: &str| {
+        layout
+            .bytes()
+            .map(|block| (block != b'.').then(|| u64::from(block - b'0')))
+            .collect::<Vec<_>>()
+    };
+
+    let disk = Disk::parse(data.as_bytes());
+    let compaction = disk.compact(CompactionStrategy::BlockWise);
+    assert_eq!(compaction.checksum, 1928);
+    assert_eq!(compaction.moves, 12);
+    assert_eq!(
+        compaction.layout,
+        layout("0099811188827773336446555566..............")
+    );
+    let compaction = disk.compact(CompactionStrategy::FirstFit);
+    assert_eq!(compaction.checksum, 2858);
+    assert_eq!(compaction.moves, 4);
+    assert_eq!(
+        compaction.layout,
+        layout("00992111777.44.333....5555.6666.....8888..")
+    );
+    assert_eq!(disk.compact(CompactionStrategy::BestFit), compaction);
+    let compaction = disk.compact(CompactionStrategy::Defragment);
+    assert_eq!(compaction.checksum, 2453);
+    assert_eq!(compaction.moves, 9);
+    assert_eq!(
+        compaction.layout,
+        layout("0011123334455556666777888899..............")
+    );
+
+    let compaction = disk.compact(CompactionStrategy::FirstFit);
+    let rendered = render_layout(&compaction.layout);
+    assert_eq!(
+        rendered.as_deref(),
+        Some("00992111777.44.333....5555.6666.....8888..")
+    );
+    let compacted = Disk::from_layout(&parse_layout(rendered.unwrap().as_bytes()).unwrap());
+    assert_eq!(compacted.layout(), compaction.layout);
+    // File 9 comes before file 2
+    assert_eq!(compacted.to_dense(), None);
+    assert_eq!(
+        Disk::from_layout(&disk.layout()).to_dense().as_deref(),
+        Some(data)
+    );
+
+    let disk = Disk::parse("13111".as_bytes());
+    let compaction = disk.compact(CompactionStrategy::FirstFit);
+    assert_eq!(compaction.layout, layout("021...."));
+    assert_eq!(compaction.checksum, 4);
+    let compaction = disk.compact(CompactionStrategy::BestFit);
+    assert_eq!(compaction.layout, layout("01...2."));
+    assert_eq!(compaction.checksum, 11);
+    assert_eq!(compaction.moves, 2);
 }
 
 #[test]
@@ -158,6 +265,129 @@ fn day10() {
 fn day11() {
     let data = r#"125 17"#;
     assert_eq!(aoc2024::day11::part1(data.as_bytes()), 55312);
+
+    let mut stones = aoc2024::day11::parse_stones(data.as_bytes());
+    aoc2024::day11::evolve(&mut stones, 6, &StoneRules::puzzle());
+    assert_eq!(stones.into_values().sum::<usize>(), 22);
+
+    // Digits count up, and 9 becomes 1, 2 and 3
+    let rules = StoneRules::new()
+        .with_rule(|n| *n == 9, |_| vec![1, 2, 3])
+        .with_rule(|n| *n < 9, |n| vec![n + 1]);
+    let mut stones = HashMap::from([(0, 1), (7, 2)]);
+    let mut expanded = vec![0, 7, 7];
+    for _ in 0..20 {
+        aoc2024::day11::evolve(&mut stones, 1, &rules);
+        expanded = expanded
+            .into_iter()
+            .flat_map(|stone| rules.apply(&stone))
+            .collect();
+        assert_eq!(stones.values().sum::<usize>(), expanded.len());
+    }
+    assert_eq!(expanded.len(), 59);
+
+    let mut stones = aoc2024::day11::parse_big_stones(data.as_bytes());
+    aoc2024::day11::evolve(&mut stones, 25, &StoneRules::puzzle());
+    assert_eq!(
+        aoc2024::day11::count_stones(&stones),
+        Some(BigUint::from(55312u32))
+    );
+    aoc2024::day11::evolve(&mut stones, 50, &StoneRules::puzzle());
+    let mut small_stones = aoc2024::day11::parse_stones(data.as_bytes());
+    aoc2024::day11::evolve(&mut small_stones, 75, &StoneRules::puzzle());
+    assert_eq!(
+        aoc2024::day11::count_stones(&stones),
+        aoc2024::day11::count_stones(&small_stones).map(BigUint::from)
+    );
+
+    assert_eq!(
+        aoc2024::day11::try_evolve(&mut small_stones, 200, &StoneRules::puzzle()),
+        Err(EvolveError::CountOverflow { blink: 37 })
+    );
+    let mut stones = aoc2024::day11::parse_big_stones(data.as_bytes());
+    aoc2024::day11::evolve(&mut stones, 300, &StoneRules::puzzle());
+    assert!(aoc2024::day11::count_stones(&stones).unwrap() > BigUint::from(u128::MAX));
+
+    let rules = StoneRules::new()
+        .with_checked_rule(|_| true, |n: &u64| n.checked_mul(2024).map(|n| vec![n]));
+    let mut stones = HashMap::from([(1, 1usize)]);
+    assert_eq!(
+        aoc2024::day11::try_evolve(&mut stones, 10, &rules),
+        Err(EvolveError::ValueOverflow { blink: 6 })
+    );
+    assert_eq!(stones, HashMap::from([(2024u64.pow(5), 1)]));
+
+    let stones = aoc2024::day11::parse_stones(data.as_bytes());
+    let transitions =
+        StoneTransitions::build(stones.keys().copied(), &StoneRules::puzzle(), 10_000).unwrap();
+    assert_eq!(transitions.len(), 76);
+    // 2^61 - 1
+    let modulus = 2_305_843_009_213_693_951;
+    let mut evolved = stones.clone();
+    for blinks in 0..=75 {
+        let count = u64::try_from(evolved.values().sum::<usize>()).unwrap();
+        assert_eq!(transitions.count_after(&stones, blinks, modulus), count);
+        assert_eq!(
+            transitions.count_after_by_matrix(&stones, blinks, modulus),
+            count
+        );
+        // Not a prime, so through the matrix
+        assert_eq!(
+            transitions.count_after(&stones, blinks, 1_000_000_000_000_000_000),
+            count
+        );
+        aoc2024::day11::evolve(&mut evolved, 1, &StoneRules::puzzle());
+    }
+    assert_eq!(
+        transitions.count_after(&stones, 1_000_000_000_000, modulus),
+        transitions.count_after_by_matrix(&stones, 1_000_000_000_000, modulus)
+    );
+
+    // Past 2n blinks, the count comes from the recurrence
+    let mut big_stones = aoc2024::day11::parse_big_stones(data.as_bytes());
+    let mut evolved_blinks = 0;
+    for blinks in [200, 201, 300] {
+        aoc2024::day11::evolve(
+            &mut big_stones,
+            blinks - evolved_blinks,
+            &StoneRules::puzzle(),
+        );
+        evolved_blinks = blinks;
+        assert_eq!(
+            BigUint::from(transitions.count_after(&stones, blinks as u64, modulus)),
+            aoc2024::day11::count_stones(&big_stones).unwrap() % modulus
+        );
+    }
+
+    let growth_rate = transitions.growth_rate();
+    assert!((1.5..1.6).contains(&growth_rate));
+    let log10_count = transitions.estimate_log10_count(&stones, 75);
+    assert!((log10_count - 65601038650482f64.log10()).abs() < 1e-6);
+    let log10_count = transitions.estimate_log10_count(&stones, 1_000_000_000_000);
+    assert!((log10_count / 1e12 - growth_rate.log10()).abs() < 1e-6);
+
+    let rules = StoneRules::new().with_rule(|_: &u64| true, |n| vec![n + 1]);
+    assert!(StoneTransitions::build([0], &rules, 100).is_none());
+
+    // A realistic input reaches thousands of values, too many to square their
+    // matrix
+    let data = "0 7 6618216 26481 885 42 202642 8791";
+    let stones = aoc2024::day11::parse_stones(data.as_bytes());
+    let transitions =
+        StoneTransitions::build(stones.keys().copied(), &StoneRules::puzzle(), 10_000).unwrap();
+    assert_eq!(transitions.len(), 3878);
+    let mut evolved = stones.clone();
+    for blinks in 0..=75 {
+        assert_eq!(
+            transitions.count_after(&stones, blinks, modulus),
+            u64::try_from(evolved.values().sum::<usize>()).unwrap()
+        );
+        aoc2024::day11::evolve(&mut evolved, 1, &StoneRules::puzzle());
+    }
+    assert_eq!(
+        transitions.count_after(&stones, 1_000_000_000_000, modulus),
+        569_827_235_306_896_078
+    );
 }
 
 #[test]
@@ -177,6 +407,23 @@ OOOOO"#;
     assert_eq!(aoc2024::day12::part1(data.as_bytes()), 772);
     assert_eq!(aoc2024::day12::part2(data.as_bytes()), 436);
 
+    let regions = aoc2024::day12::regions(data.as_bytes());
+    assert_eq!(regions.len(), 5);
+    let outer = &regions[0];
+    assert_eq!(outer.plant, b'O');
+    assert_eq!(outer.cells.len(), 21);
+    assert_eq!((outer.area, outer.perimeter, outer.sides), (21, 36, 20));
+    assert_eq!(outer.bounding_box, (Coord::new(0, 0), Coord::new(4, 4)));
+    assert_eq!(outer.holes, BTreeSet::from([1, 2, 3, 4]));
+    assert_eq!(outer.adjacent, BTreeSet::from([1, 2, 3, 4]));
+    for inner in &regions[1..] {
+        assert_eq!(inner.plant, b'X');
+        assert_eq!((inner.area, inner.perimeter, inner.sides), (1, 4, 4));
+        assert_eq!(inner.bounding_box.0, inner.bounding_box.1);
+        assert!(inner.holes.is_empty());
+        assert_eq!(inner.adjacent, BTreeSet::from([0]));
+    }
+
     let data = r#"EEEEE
 EXXXX
 EEEEE
@@ -184,6 +431,12 @@ EXXXX
 EEEEE"#;
     assert_eq!(aoc2024::day12::part2(data.as_bytes()), 236);
 
+    // The X regions touch the border of the map
+    let regions = aoc2024::day12::regions(data.as_bytes());
+    assert_eq!(regions.len(), 3);
+    assert!(regions.iter().all(|region| region.holes.is_empty()));
+    assert_eq!(regions[0].adjacent, BTreeSet::from([1, 2]));
+
     let data = r#"AAAAAA
 AAABBA
 AAABBA
@@ -192,6 +445,14 @@ ABBAAA
 AAAAAA"#;
     assert_eq!(aoc2024::day12::part2(data.as_bytes()), 368);
 
+    let regions = aoc2024::day12::regions(data.as_bytes());
+    assert_eq!(regions.len(), 3);
+    assert_eq!(regions[0].holes, BTreeSet::from([1, 2]));
+    assert_eq!(
+        regions[2].bounding_box,
+        (Coord::new(3, 1), Coord::new(4, 2))
+    );
+
     let data = r#"RRRRIICCFF
 RRRRIICCCF
 VVRRRCCFFF
@@ -224,6 +485,152 @@ Button A: X+69, Y+23
 Button B: X+27, Y+71
 Prize: X=18641, Y=10279"#;
     assert_eq!(aoc2024::day13::part1(data.as_bytes()), 480);
+
+    let machine = Machine::new((94, 34), (22, 67), (8400, 5400), 0..=100);
+    assert_eq!(
+        machine.solve(),
+        Some(Solution {
+            presses_a: 80,
+            presses_b: 40,
+            cost: 280
+        })
+    );
+
+    // Button A doesn't move along Y
+    let machine = Machine::new((3, 0), (1, 2), (8, 4), 0..=100);
+    assert_eq!(machine.solve().map(|solution| solution.cost), Some(8));
+
+    // Collinear buttons, B is cheaper per unit of distance unless the costs
+    // are changed
+    let machine = Machine::new((2, 2), (1, 1), (11, 11), 0..=100);
+    assert_eq!(
+        machine.solve(),
+        Some(Solution {
+            presses_a: 0,
+            presses_b: 11,
+            cost: 11
+        })
+    );
+    assert_eq!(
+        machine.clone().with_costs(1, 1).solve(),
+        Some(Solution {
+            presses_a: 5,
+            presses_b: 1,
+            cost: 6
+        })
+    );
+    let machine = Machine::new((2, 2), (1, 1), (11, 11), 0..=7);
+    assert_eq!(
+        machine.solve(),
+        Some(Solution {
+            presses_a: 2,
+            presses_b: 7,
+            cost: 13
+        })
+    );
+    assert_eq!(
+        Machine::new((2, 2), (4, 4), (11, 11), 0..=100).solve(),
+        None
+    );
+    assert_eq!(Machine::new((2, 2), (1, 1), (3, 4), 0..=100).solve(), None);
+
+    let costs = |padding, range| {
+        parse_claw_machines(data.as_bytes(), padding, range)
+            .into_iter()
+            .flat_map(|machine| machine.solve().unwrap())
+            .map(|solution| solution.cost)
+            .sum::<isize>()
+    };
+    assert_eq!(costs(0, 0..=100), 480);
+    assert_eq!(
+        costs(10000000000000, 0..=10000000000000),
+        aoc2024::day13::part2(data.as_bytes())
+    );
+
+    let data = r#"Button A: X+1, Y+0, Z+1
+Button B: X+0, Y+1, Z+1
+Button C: X+1, Y+1, Z+0
+Prize: X=3, Y=5, Z=4
+
+Button A: X+1, Y+1
+Button B: X+1, Y+0
+Button C: X+0, Y+1
+Prize: X=3, Y=4"#;
+    let machines = parse_claw_machines(data.as_bytes(), 0, 0..=100);
+    assert_eq!(
+        machines[0].solve(),
+        Ok(Some(ClawSolution {
+            presses: vec![1, 3, 2],
+            cost: 8
+        }))
+    );
+    assert_eq!(
+        machines[1].solve(),
+        Ok(Some(ClawSolution {
+            presses: vec![0, 3, 4],
+            cost: 7
+        }))
+    );
+    assert_eq!(
+        machines[1].clone().with_costs(vec![1, 1, 1]).solve(),
+        Ok(Some(ClawSolution {
+            presses: vec![3, 0, 1],
+            cost: 4
+        }))
+    );
+
+    // A single dependent button is solved without going through its presses,
+    // no button can be pressed more than `padding` times
+    let padding = 10000000000000;
+    let machines = parse_claw_machines(data.as_bytes(), padding, 0..=padding);
+    assert_eq!(
+        machines[1].solve(),
+        Ok(Some(ClawSolution {
+            presses: vec![4, padding - 1, padding],
+            cost: 2 * padding + 11
+        }))
+    );
+    assert_eq!(
+        machines[1].clone().with_costs(vec![1, 1, 1]).solve(),
+        Ok(Some(ClawSolution {
+            presses: vec![padding, 3, 4],
+            cost: padding + 7
+        }))
+    );
+    let machine = ClawMachine::new(
+        vec![vec![2, 2], vec![1, 1]],
+        vec![padding + 11; 2],
+        0..=padding,
+    );
+    let solution = Machine::new((2, 2), (1, 1), (padding + 11, padding + 11), 0..=padding)
+        .solve()
+        .unwrap();
+    assert_eq!(
+        machine.solve(),
+        Ok(Some(ClawSolution {
+            presses: vec![solution.presses_a, solution.presses_b],
+            cost: solution.cost
+        }))
+    );
+
+    // Two free presses over the whole range are cut by the relaxation, or
+    // give up once the search goes through too many nodes
+    let machine = ClawMachine::new(
+        vec![vec![1, 0], vec![0, 1], vec![1, 1], vec![2, 1]],
+        vec![padding + 7, padding + 5],
+        0..=padding,
+    );
+    assert_eq!(
+        machine.solve(),
+        Ok(Some(ClawSolution {
+            presses: vec![0, padding / 2 + 1, 1, padding / 2 + 3],
+            cost: padding + 5
+        }))
+    );
+    assert_eq!(
+        machine.with_search_limit(1).solve(),
+        Err(ClawError::SearchLimit { nodes: 1 })
+    );
 }
 
 #[test]
@@ -241,6 +648,80 @@ p=7,3 v=-1,2
 p=2,4 v=2,-3
 p=9,5 v=-3,-3"#;
     assert_eq!(aoc2024::day14::part1_for_testing(data.as_bytes()), 12);
+
+    let simulation = Simulation::parse(data.as_bytes(), (11, 7));
+    assert_eq!(simulation.period(), 77);
+    let frame = simulation.frame(100);
+    assert_eq!(
+        frame.render(),
+        r#"......2..1.
+...........
+1..........
+.11........
+.....1.....
+...12......
+.1....1...."#
+    );
+    assert_eq!(frame.longest_horizontal_run(), 2);
+    assert_eq!(simulation.frame(100 % 77).counts, frame.counts);
+    assert_eq!(simulation.safety_factor(100), 12);
+    assert_eq!(simulation.safety_factor(100 + 77 * 1_000_000_000_000), 12);
+    let time = simulation.distinct_cells_time().unwrap();
+    assert!(simulation
+        .frame(time)
+        .counts
+        .iter()
+        .all(|count| *count <= 1));
+    assert!((0..time).all(|time| simulation.frame(time).counts.iter().any(|count| *count > 1)));
+    // Robots moving together never leave each other
+    let simulation = Simulation::parse(
+        "p=1,2 v=3,-1\np=4,0 v=2,2\np=1,2 v=3,-1".as_bytes(),
+        (11, 7),
+    );
+    assert_eq!(simulation.distinct_cells_time(), None);
+
+    let mut pbm = Vec::new();
+    Frame {
+        time: 3,
+        width: 3,
+        height: 2,
+        counts: vec![0, 2, 1, 1, 0, 0],
+    }
+    .write_pbm(&mut pbm)
+    .unwrap();
+    assert_eq!(
+        String::from_utf8(pbm).unwrap(),
+        "P1\n# t=3\n3 2\n0 1 1\n1 0 0\n"
+    );
+
+    // A filled 8x6 rectangle drawn at second 1234, among robots moving at
+    // random
+    let mut state = 0x2024_u64;
+    let mut random = |modulus: u64| (xorshift(&mut state) % modulus) as i64;
+    let robot = |(x, y): (i64, i64), (vx, vy): (i64, i64)| {
+        format!(
+            "p={},{} v={vx},{vy}",
+            (x - vx * 1234).rem_euclid(101),
+            (y - vy * 1234).rem_euclid(103)
+        )
+    };
+    let data = (0..200)
+        .map(|i| {
+            let velocity = (random(201) - 100, random(207) - 103);
+            if i < 48 {
+                robot((40 + i % 8, 50 + i / 8), velocity)
+            } else {
+                robot((random(101), random(103)), velocity)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let simulation = Simulation::parse(data.as_bytes(), (101, 103));
+    assert_eq!(simulation.min_variance_times(), (1234 % 101, 1234 % 103));
+    assert_eq!(simulation.crt_time(), Some(1234));
+    assert_eq!(simulation.horizontal_run_time(8), Some(1234));
+    assert_eq!(aoc2024::day14::part2(data.as_bytes()), 1234);
 }
 
 #[test]
@@ -257,6 +738,120 @@ fn day15() {
 <^^>>>vv<v>>v<<"#;
     assert_eq!(aoc2024::day15::part1(data.as_bytes()), 2028);
 
+    // Same boxes as after all the moves of the puzzle, in fewer moves
+    let (warehouse, moves) = parse_puzzle(data.as_bytes(), Coord::new(1, 1));
+    let mut target = warehouse.clone();
+    for robot_move in moves {
+        target.apply(robot_move);
+    }
+    let MoveSearch::Found(moves) = warehouse.find_moves(&target.boxes(), 100_000) else {
+        panic!("Target should be reachable");
+    };
+    assert_eq!(moves.len(), 10);
+    let mut reached = warehouse.clone();
+    for robot_move in moves {
+        reached.apply(robot_move);
+    }
+    assert_eq!(reached.boxes(), target.boxes());
+    assert_eq!(
+        warehouse.find_moves(&target.boxes(), 10),
+        MoveSearch::NodeLimit
+    );
+
+    // The robot can't get past the box to push it back
+    let corridor = r#"#######
+#@.O..#
+#######
+
+<"#;
+    let (warehouse, _) = parse_puzzle(corridor.as_bytes(), Coord::new(1, 1));
+    assert_eq!(
+        warehouse.find_moves(&[Coord::new(1, 2)], 100_000),
+        MoveSearch::Impossible
+    );
+    assert_eq!(
+        warehouse.find_moves(&[Coord::new(1, 5)], 100_000),
+        MoveSearch::Found(vec![Direction::East; 3])
+    );
+
+    let (mut warehouse, moves) = parse_puzzle(data.as_bytes(), Coord::new(1, 1));
+    let initial = warehouse.render();
+    for robot_move in &moves {
+        warehouse.apply(*robot_move);
+    }
+    let last = warehouse.render();
+    assert_eq!(
+        last,
+        r#"########
+#....OO#
+##.....#
+#.....O#
+#.#O@..#
+#...O..#
+#...O..#
+########"#
+    );
+    assert_eq!(warehouse.compute_gps(), 2028);
+    while warehouse.undo().is_some() {}
+    assert_eq!(warehouse.render(), initial);
+    assert!(warehouse.history().is_empty());
+    while warehouse.redo().is_some() {}
+    assert_eq!(warehouse.render(), last);
+    assert_eq!(warehouse.history().len(), moves.len());
+
+    let data = r#"#######
+#...#.#
+#.....#
+#..OO@#
+#..O..#
+#.....#
+#######
+
+<vv<<^^<<^^"#;
+    let (mut warehouse, moves) = parse_puzzle(data.as_bytes(), Coord::new(1, 2));
+    let initial = warehouse.render();
+    assert_eq!(
+        warehouse.apply(moves[0]),
+        &Step {
+            robot_move: Direction::West,
+            robot: Coord::new(3, 10),
+            moved: true,
+            boxes: vec![Coord::new(3, 8), Coord::new(3, 6)],
+        }
+    );
+    assert_eq!(
+        warehouse.render(),
+        r#"##############
+##......##..##
+##..........##
+##...[][]@..##
+##....[]....##
+##..........##
+##############"#
+    );
+    for robot_move in &moves[1..] {
+        warehouse.apply(*robot_move);
+    }
+    let last = r#"##############
+##...[].##..##
+##...@.[]...##
+##....[]....##
+##..........##
+##..........##
+##############"#;
+    assert_eq!(warehouse.render(), last);
+
+    // Pushing up into the wall is blocked
+    let step = warehouse.apply(Direction::North);
+    assert!(!step.moved && step.boxes.is_empty());
+    assert_eq!(warehouse.undo().map(|step| step.moved), Some(false));
+    warehouse.undo();
+    warehouse.undo();
+    warehouse.apply(Direction::East);
+    assert!(warehouse.redo().is_none());
+    while warehouse.undo().is_some() {}
+    assert_eq!(warehouse.render(), initial);
+
     let data = r#"##########
 #..O..O.O#
 #......O.#
@@ -280,6 +875,45 @@ vvv<<^>^v^^><<>>><>^<<><^vv^^<>vvv<>><^^v>^>vv<>v<<<<v<^v>^<^^>>>^<v<v
 v^^>>><<^^<>>^v^<v^vv<>v^<<>^<^v^v><^<<<><<^<v><v<>vv>>v><v^<vv<>v^<<^"#;
     assert_eq!(aoc2024::day15::part1(data.as_bytes()), 10092);
     assert_eq!(aoc2024::day15::part2(data.as_bytes()), 9021);
+
+    for (scale, gps) in [(Coord::new(1, 1), 10092), (Coord::new(1, 2), 9021)] {
+        let (mut warehouse, moves) = parse_puzzle(data.as_bytes(), scale);
+        for robot_move in moves {
+            warehouse.apply(robot_move);
+        }
+        assert_eq!(warehouse.compute_gps(), gps);
+    }
+
+    // Boxes 2 rows high and 3 columns wide, the last push is blocked by the
+    // wall
+    let data = r#"######
+#....#
+#.OO.#
+#..@.#
+######
+
+^^<<^>>>>>"#;
+    let (mut warehouse, moves) = parse_puzzle(data.as_bytes(), Coord::new(2, 3));
+    assert_eq!(warehouse.robot(), Coord::new(6, 9));
+    assert_eq!(warehouse.apply(moves[0]).boxes, vec![Coord::new(4, 9)]);
+    for robot_move in &moves[1..] {
+        warehouse.apply(*robot_move);
+    }
+    assert_eq!(
+        warehouse.render(),
+        r#"##################
+##################
+###.........[=]###
+###........@[=]###
+###.[=]........###
+###.[=]........###
+###............###
+###............###
+##################
+##################"#
+    );
+    assert!(!warehouse.history().last().unwrap().moved);
+    assert_eq!(warehouse.compute_gps(), 212 + 404);
 }
 
 #[test]
@@ -378,6 +1012,36 @@ fn day18() {
         aoc2024::day18::part2_for_test(data.as_bytes()),
         Coord::new(6, 1)
     );
+
+    let reports = aoc2024::day18::stream(data.as_bytes(), Coord::new(6, 6)).collect::<Vec<_>>();
+    assert_eq!(reports[11].1, Some(22));
+    let cut = reports
+        .iter()
+        .position(|(_, steps)| steps.is_none())
+        .unwrap();
+    assert_eq!(reports[cut].0, Coord::new(1, 6));
+    assert!(reports[cut..].iter().all(|(_, steps)| steps.is_none()));
+
+    let mut falling_bytes = FallingBytes::new(Coord::new(7, 7));
+    assert_eq!(falling_bytes.steps(), Some(12));
+    for (byte, _) in &reports {
+        falling_bytes.push(*byte);
+    }
+    assert_eq!(falling_bytes.cut_by(), Some(cut));
+
+    // One more byte falls after each step, the straight route is only free
+    // while the first bytes fall
+    let route = aoc2024::day18::route_while_falling(data.as_bytes(), Coord::new(6, 6), 3).unwrap();
+    assert_eq!(route.arrival, 12);
+    assert_eq!(route.path.len(), 13);
+    assert_eq!(route.path.first(), Some(&Coord::new(0, 0)));
+    assert_eq!(route.path.last(), Some(&Coord::new(6, 6)));
+    let route = aoc2024::day18::route_while_falling(data.as_bytes(), Coord::new(6, 6), 4).unwrap();
+    assert_eq!(route.arrival, 22);
+    assert_eq!(
+        aoc2024::day18::route_while_falling(data.as_bytes(), Coord::new(6, 6), 10),
+        None
+    );
 }
 
 #[test]
@@ -395,6 +1059,153 @@ bbrgwb
 "#;
     assert_eq!(aoc2024::day19::part1(data.as_bytes()), 6);
     assert_eq!(aoc2024::day19::part2(data.as_bytes()), 16);
+
+    let (towels, patterns) = aoc2024::day19::parse_input(data.as_bytes());
+    assert_eq!(patterns.len(), 8);
+    let brwrr = towels.match_pattern(b"brwrr");
+    assert_eq!(brwrr.arrangements, 2);
+    assert_eq!(brwrr.fewest, Some(vec![&b"br"[..], b"wr", b"r"]));
+    assert_eq!(brwrr.example, Some(vec![&b"b"[..], b"r", b"wr", b"r"]));
+    let ubwu = towels.match_pattern(b"ubwu");
+    assert_eq!(
+        (ubwu.arrangements, ubwu.fewest, ubwu.example),
+        (0, None, None)
+    );
+
+    let lexicographic = towels
+        .arrangements(b"gbbr", ArrangementOrder::Lexicographic)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        lexicographic,
+        vec![
+            vec![&b"g"[..], b"b", b"b", b"r"],
+            vec![&b"g"[..], b"b", b"br"],
+            vec![&b"gb"[..], b"b", b"r"],
+            vec![&b"gb"[..], b"br"],
+        ]
+    );
+    let fewest = towels
+        .arrangements(b"gbbr", ArrangementOrder::FewestTowels)
+        .skip(1)
+        .take(2)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        fewest,
+        vec![vec![&b"g"[..], b"b", b"br"], vec![&b"gb"[..], b"b", b"r"]]
+    );
+
+    let usage = towels.usage(&patterns);
+    assert_eq!(
+        towels
+            .towels()
+            .iter()
+            .map(|towel| towel.as_slice())
+            .zip(usage)
+            .take(3)
+            .collect::<Vec<_>>(),
+        vec![(&b"r"[..], 24), (b"wr", 2), (b"b", 12)]
+    );
+    assert_eq!(towels.redundant(), vec![&b"rb"[..], b"gb", b"br"]);
+
+    let minimal = towels.minimal_set(&patterns, 1_000);
+    assert!(minimal.minimum);
+    assert_eq!(minimal.towels, vec![&b"r"[..], b"wr", b"b", b"g", b"bwu"]);
+    assert_eq!(
+        minimal.status,
+        vec![
+            TowelStatus::Kept,
+            TowelStatus::Kept,
+            TowelStatus::Kept,
+            TowelStatus::Kept,
+            TowelStatus::Kept,
+            TowelStatus::Redundant,
+            TowelStatus::Redundant,
+            TowelStatus::Redundant,
+        ]
+    );
+
+    // The search is cut short, the greedy subset is already the smallest
+    let greedy = towels.minimal_set(&patterns, 0);
+    assert!(!greedy.minimum);
+    assert_eq!(greedy.towels, minimal.towels);
+
+    // A towel made of other towels is kept when it alone is enough.
+    // Impossible patterns are ignored.
+    let towels = Towels::new(["r", "b", "rb", "g"]);
+    let minimal = towels.minimal_set(&[b"rbrb", b"gbx"], 1_000);
+    assert!(minimal.minimum);
+    assert_eq!(minimal.towels, vec![&b"rb"[..]]);
+    assert_eq!(
+        minimal.status,
+        vec![
+            TowelStatus::Unused,
+            TowelStatus::Unused,
+            TowelStatus::Kept,
+            TowelStatus::Unused,
+        ]
+    );
+
+    // Puzzle sized input, without the single stripe g and u towels, and with
+    // designs made of random towels, a quarter of them made impossible
+    let mut state = 0x2024_u64;
+    let mut random = |modulus: usize| xorshift(&mut state) as usize % modulus;
+    let towels = (0..450)
+        .map(|_| {
+            let len = 1 + random(8);
+            (0..len).map(|_| b"wubrg"[random(5)]).collect::<Vec<_>>()
+        })
+        .filter(|towel| towel != b"g" && towel != b"u")
+        .collect::<Vec<_>>();
+    let designs = (0..400)
+        .map(|design| {
+            let mut stripes = Vec::new();
+            while stripes.len() < 40 {
+                stripes.extend(&towels[random(towels.len())]);
+            }
+            if design % 4 == 0 {
+                stripes.push(b'g');
+            }
+            stripes
+        })
+        .collect::<Vec<_>>();
+    let designs = designs.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    let towels = Towels::new(&towels);
+    // Too many towels for the search to go through, the subset is only
+    // minimal for inclusion
+    let minimal = towels.minimal_set(&designs, 1_000);
+    assert!(!minimal.minimum);
+    assert!(minimal.towels.len() < towels.towels().len() / 4);
+    let kept = Towels::new(&minimal.towels);
+    for design in &designs {
+        assert_eq!(kept.is_possible(design), towels.is_possible(design));
+    }
+    for towel in &minimal.towels {
+        let without = Towels::new(minimal.towels.iter().filter(|other| *other != towel));
+        assert!(designs
+            .iter()
+            .any(|design| kept.is_possible(design) && !without.is_possible(design)));
+    }
+
+    // Paging far into a huge number of arrangements
+    let towels = Towels::new(["r", "rr", "rrr"]);
+    let pattern = [b'r'; 60];
+    let arrangements = towels.arrangements(&pattern, ArrangementOrder::Lexicographic);
+    assert_eq!(arrangements.len(), towels.count_arrangements(&pattern));
+    let page = arrangements
+        .skip(1_000_000_000_000)
+        .take(2)
+        .collect::<Vec<_>>();
+    assert_eq!(page.len(), 2);
+    assert!(page[0] < page[1]);
+
+    // Any byte can be a stripe
+    let towels = Towels::new(["xy", "z", "xyz", "7", "xy"]);
+    assert_eq!(towels.towels().len(), 4);
+    let matched = towels.match_pattern(b"xyzz7");
+    assert_eq!(matched.arrangements, 2);
+    assert_eq!(matched.fewest, Some(vec![&b"xyz"[..], b"z", b"7"]));
+    assert_eq!(matched.example, Some(vec![&b"xy"[..], b"z", b"z", b"7"]));
+    assert!(!towels.is_possible(b"xyy"));
 }
 
 #[test]
@@ -416,6 +1227,53 @@ fn day20() {
 ###############"#;
     assert_eq!(aoc2024::day20::part1(data.as_bytes()), 0);
     assert_eq!(aoc2024::day20::part2(data.as_bytes()), 0);
+
+    let cheats = find_cheats(data.as_bytes(), 2, 1);
+    assert_eq!(
+        savings_histogram(&cheats),
+        BTreeMap::from([
+            (2, 14),
+            (4, 14),
+            (6, 2),
+            (8, 4),
+            (10, 2),
+            (12, 3),
+            (20, 1),
+            (36, 1),
+            (38, 1),
+            (40, 1),
+            (64, 1),
+        ])
+    );
+    assert_eq!(
+        cheats.iter().find(|cheat| cheat.savings == 64),
+        Some(&Cheat {
+            start: Coord::new(7, 7),
+            end: Coord::new(7, 5),
+            savings: 64,
+        })
+    );
+
+    let cheats = find_cheats(data.as_bytes(), 20, 50);
+    assert_eq!(
+        savings_histogram(&cheats),
+        BTreeMap::from([
+            (50, 32),
+            (52, 31),
+            (54, 29),
+            (56, 39),
+            (58, 25),
+            (60, 23),
+            (62, 20),
+            (64, 19),
+            (66, 12),
+            (68, 14),
+            (70, 12),
+            (72, 22),
+            (74, 4),
+            (76, 3),
+        ])
+    );
 }
 
 #[test]
@@ -484,6 +1342,10 @@ td-yn
 "#;
     assert_eq!(aoc2024::day23::part1(data.as_bytes()), 7);
     assert_eq!(aoc2024::day23::part2(data.as_bytes()), "co,de,ka,ta");
+
+    // Separate networks, a group on the smaller ones can't be the largest
+    let data = format!("{data}aa-ab\nxa-xb\nxb-xc\nxc-xa\n");
+    assert_eq!(aoc2024::day23::part2(data.as_bytes()), "co,de,ka,ta");
 }
 
 #[test]